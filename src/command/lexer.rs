@@ -0,0 +1,72 @@
+use super::parser::ParseError;
+
+/// TokenKind is the kind of a token found on a line of a .cmmd script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A run of characters without whitespace, quotes or semicolons.
+    Word(String),
+    /// A double-quoted string literal with its escapes resolved.
+    Str(String),
+    /// A semicolon ending a statement.
+    Semicolon,
+}
+
+/// Token is a lexical unit of a line, together with the byte range it covers in the line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Function to split a line into tokens
+/// Words are separated by whitespace, string literals are delimited by double quotes and may
+/// contain `\"` and `\\` escapes, and semicolons separate statements.
+pub fn tokenize(line: &str, line_number: usize) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            chars.next();
+            tokens.push(Token { kind: TokenKind::Semicolon, start, end: start + 1 });
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            let mut end = None;
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        end = Some(i + 1);
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                        Some((_, other)) => {
+                            value.push('\\');
+                            value.push(other);
+                        }
+                        None => value.push('\\'),
+                    },
+                    _ => value.push(c),
+                }
+            }
+            let end = end.ok_or_else(|| ParseError::new(line_number, line, start, "unterminated string literal"))?;
+            tokens.push(Token { kind: TokenKind::Str(value), start, end });
+        } else {
+            let mut end = line.len();
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_whitespace() || c == ';' || c == '"' {
+                    end = i;
+                    break;
+                }
+                chars.next();
+            }
+            tokens.push(Token { kind: TokenKind::Word(line[start..end].to_string()), start, end });
+        }
+    }
+
+    Ok(tokens)
+}
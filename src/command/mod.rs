@@ -0,0 +1,123 @@
+use std::fmt;
+
+use crate::{DataFormat, quote_data};
+
+pub mod lexer;
+pub mod parser;
+
+pub use parser::{ParseError, Statement, parse_format, parse_line, parse_script};
+
+/// Command is a single parsed statement of a .cmmd script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Allocates a block of `size` bytes and stores `data` in it.
+    Insert { size: usize, data: Vec<u8> },
+    /// Prints the block with the given ID, in the manager's default format if none is given.
+    Read { id: usize, format: Option<DataFormat> },
+    /// Frees the block with the given ID.
+    Delete { id: usize },
+    /// Replaces the data of the block with the given ID.
+    Update { id: usize, data: Vec<u8> },
+    /// Replaces the data of the block with the given ID, keeping the ID if the block has to move.
+    UpdateStable { id: usize, data: Vec<u8> },
+    /// Prints every allocated and free block, in the manager's default format if none is given.
+    Dump { format: Option<DataFormat> },
+    /// Prints the bytes of the heap like xxd, the whole heap or `len` bytes from an address.
+    Hexdump { range: Option<(usize, usize)> },
+    /// Prints the heap as a bar of cells that are allocated, partially used or free.
+    Map,
+    /// Prints the fragmentation and utilization of the heap, as text unless JSON is asked for.
+    Stats { format: Option<DataFormat> },
+    /// Checks the invariants of the heap.
+    Check,
+    /// Prints `len` bytes of the heap from an address. STRICT refuses bytes outside a single allocated block.
+    Peek { address: usize, len: usize, strict: bool },
+    /// Writes byte values to the heap from an address. STRICT refuses bytes outside a single allocated block.
+    Poke { address: usize, bytes: Vec<u8>, strict: bool },
+    /// Writes data to the heap from an address. STRICT refuses bytes outside a single allocated block.
+    Write { address: usize, data: Vec<u8>, strict: bool },
+    /// Prints which block holds an address.
+    Whois { address: usize },
+    /// Allocates a block of `size` bytes in the arena and stores `data` in it.
+    ArenaInsert { size: usize, data: Vec<u8> },
+    /// Prints the current mark of the arena.
+    ArenaMark,
+    /// Frees every arena block allocated after the given mark.
+    ArenaReset { mark: usize },
+    /// Creates a slab cache handing out objects of `object_size` bytes.
+    CacheCreate { name: String, object_size: usize },
+    /// Allocates an object from a slab cache.
+    CacheAlloc { name: String },
+    /// Gives an object back to its slab cache.
+    CacheFree { name: String, id: usize },
+    /// Switches to the allocation policy with the given name, before any block is allocated.
+    Policy { name: String },
+    /// Writes a snapshot of the whole manager state to a file.
+    Save { path: String },
+    /// Replaces the manager state with a snapshot read from a file.
+    Load { path: String },
+    /// Stops executing commands.
+    Exit,
+}
+
+/// Function to write the keyword that selects a display format
+fn format_suffix(format: &Option<DataFormat>) -> &'static str {
+    match format {
+        None => "",
+        Some(DataFormat::Text) => " TEXT",
+        Some(DataFormat::Hex) => " HEX",
+        Some(DataFormat::Json) => " JSON",
+    }
+}
+
+/// Function to write the keyword that asks for strict raw access
+fn strict_suffix(strict: bool) -> &'static str {
+    if strict { " STRICT" } else { "" }
+}
+
+/// Function to write a file path so that it is read back as a single token
+fn quote_path(path: &str) -> String {
+    if !path.is_empty() && !path.contains(|c: char| c.is_whitespace() || c == ';' || c == '"' || c == '\\') {
+        return path.to_string();
+    }
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Implement Display for Command
+/// Commands are displayed in script syntax, so the output can be parsed again.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Insert { size, data } => write!(f, "INSERT {} {}", size, quote_data(data)),
+            Command::Read { id, format } => write!(f, "READ {}{}", id, format_suffix(format)),
+            Command::Delete { id } => write!(f, "DELETE {}", id),
+            Command::Update { id, data } => write!(f, "UPDATE {} {}", id, quote_data(data)),
+            Command::UpdateStable { id, data } => write!(f, "UPDATE STABLE {} {}", id, quote_data(data)),
+            Command::Dump { format } => write!(f, "DUMP{}", format_suffix(format)),
+            Command::Hexdump { range: None } => write!(f, "HEXDUMP"),
+            Command::Hexdump { range: Some((start, len)) } => write!(f, "HEXDUMP 0x{:04X} {}", start, len),
+            Command::Map => write!(f, "MAP"),
+            Command::Stats { format } => write!(f, "STATS{}", format_suffix(format)),
+            Command::Check => write!(f, "CHECK"),
+            Command::Peek { address, len, strict } => write!(f, "PEEK{} 0x{:04X} {}", strict_suffix(*strict), address, len),
+            Command::Poke { address, bytes, strict } => {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+                write!(f, "POKE{} 0x{:04X} {}", strict_suffix(*strict), address, bytes.join(" "))
+            }
+            Command::Write { address, data, strict } => {
+                write!(f, "WRITE{} 0x{:04X} {}", strict_suffix(*strict), address, quote_data(data))
+            }
+            Command::Whois { address } => write!(f, "WHOIS 0x{:04X}", address),
+            Command::ArenaInsert { size, data } => write!(f, "ARENA INSERT {} {}", size, quote_data(data)),
+            Command::ArenaMark => write!(f, "ARENA MARK"),
+            Command::ArenaReset { mark } => write!(f, "ARENA RESET {}", mark),
+            Command::CacheCreate { name, object_size } => write!(f, "CACHE CREATE {} {}", name, object_size),
+            Command::CacheAlloc { name } => write!(f, "CACHE ALLOC {}", name),
+            Command::CacheFree { name, id } => write!(f, "CACHE FREE {} {}", name, id),
+            Command::Policy { name } => write!(f, "POLICY {}", name),
+            Command::Save { path } => write!(f, "SAVE {}", quote_path(path)),
+            Command::Load { path } => write!(f, "LOAD {}", quote_path(path)),
+            Command::Exit => write!(f, "EXIT"),
+        }
+    }
+}
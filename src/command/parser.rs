@@ -0,0 +1,352 @@
+use std::error::Error;
+use std::fmt;
+
+use super::Command;
+use super::lexer::{Token, TokenKind, tokenize};
+use crate::memory_manager::policy::POLICY_NAMES;
+use crate::{DataFormat, parse_data};
+
+/// ParseError reports a problem found while parsing a .cmmd script, with its position.
+/// Lines and columns start at 1, and columns count characters rather than bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// Implement ParseError struct
+impl ParseError {
+    /// Creates a new parse error at the given byte offset of a line.
+    pub(crate) fn new(line_number: usize, line: &str, offset: usize, message: impl Into<String>) -> Self {
+        ParseError {
+            file: "<input>".to_string(),
+            line: line_number,
+            column: line[..offset].chars().count() + 1,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the same error, reported against the given file name.
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.file = file.to_string();
+        self
+    }
+}
+
+/// Implement Display for ParseError
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+/// Implement Error for ParseError
+impl Error for ParseError {}
+
+/// Statement is a command together with the line it was found on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statement {
+    pub line: usize,
+    pub command: Command,
+}
+
+/// Function to parse a single line of a script into its commands
+/// Blank lines and lines starting with '#' produce no commands, and several commands can be
+/// written on the same line when separated by semicolons.
+pub fn parse_line(line: &str, line_number: usize) -> Result<Vec<Command>, ParseError> {
+    if line.trim_start().starts_with('#') {
+        return Ok(Vec::new());
+    }
+
+    let tokens = tokenize(line, line_number)?;
+    tokens
+        .split(|token| token.kind == TokenKind::Semicolon)
+        .filter(|statement| !statement.is_empty())
+        .map(|statement| StatementParser { line, line_number, tokens: statement }.parse())
+        .collect()
+}
+
+/// Function to parse a whole script
+/// All parse errors of the script are reported, each one tagged with the given file name.
+pub fn parse_script(file: &str, source: &str) -> Result<Vec<Statement>, Vec<ParseError>> {
+    let mut statements = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        match parse_line(line, index + 1) {
+            Ok(commands) => statements.extend(commands.into_iter().map(|command| Statement { line: index + 1, command })),
+            Err(e) => errors.push(e.in_file(file)),
+        }
+    }
+
+    if errors.is_empty() { Ok(statements) } else { Err(errors) }
+}
+
+/// Function to parse the name of a display format, ignoring case
+pub fn parse_format(name: &str) -> Option<DataFormat> {
+    match name.to_uppercase().as_str() {
+        "TEXT" => Some(DataFormat::Text),
+        "HEX" => Some(DataFormat::Hex),
+        "JSON" => Some(DataFormat::Json),
+        _ => None,
+    }
+}
+
+/// Function to parse a number written in decimal or in hex with a 0x prefix
+fn parse_number(word: &str) -> Option<usize> {
+    match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(digits) => usize::from_str_radix(digits, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
+
+/// StatementParser turns the tokens of one statement into a command.
+struct StatementParser<'a> {
+    line: &'a str,
+    line_number: usize,
+    tokens: &'a [Token],
+}
+
+/// Implement StatementParser struct
+impl StatementParser<'_> {
+    /// Parses the statement, whose first token is the command keyword
+    fn parse(&self) -> Result<Command, ParseError> {
+        let keyword = match &self.tokens[0].kind {
+            TokenKind::Word(word) => word.to_uppercase(),
+            _ => return Err(self.error_at(0, "expected a command")),
+        };
+
+        match keyword.as_str() {
+            "INSERT" => Ok(Command::Insert { size: self.number(1, "size")?, data: self.payload(2)? }),
+            "READ" => {
+                let id = self.number(1, "block ID")?;
+                let format = self.format(2)?;
+                Ok(Command::Read { id, format })
+            }
+            "DELETE" => {
+                self.expect_end(2)?;
+                Ok(Command::Delete { id: self.number(1, "block ID")? })
+            }
+            "UPDATE" if self.is_word(1, "STABLE") => {
+                Ok(Command::UpdateStable { id: self.number(2, "block ID")?, data: self.payload(3)? })
+            }
+            "UPDATE" => Ok(Command::Update { id: self.number(1, "block ID")?, data: self.payload(2)? }),
+            "DUMP" => Ok(Command::Dump { format: self.format(1)? }),
+            "STATS" => Ok(Command::Stats { format: self.format(1)? }),
+            "HEXDUMP" if self.tokens.len() == 1 => Ok(Command::Hexdump { range: None }),
+            "HEXDUMP" => {
+                let range = (self.address(1)?, self.number(2, "length")?);
+                self.expect_end(3)?;
+                Ok(Command::Hexdump { range: Some(range) })
+            }
+            "MAP" => {
+                self.expect_end(1)?;
+                Ok(Command::Map)
+            }
+            "CHECK" => {
+                self.expect_end(1)?;
+                Ok(Command::Check)
+            }
+            "PEEK" | "POKE" | "WRITE" => self.raw_access(&keyword),
+            "WHOIS" => {
+                let address = self.address(1)?;
+                self.expect_end(2)?;
+                Ok(Command::Whois { address })
+            }
+            "ARENA" => self.arena(),
+            "CACHE" => self.cache(),
+            "POLICY" => Ok(Command::Policy { name: self.policy(1)? }),
+            // SAVE SNAPSHOT is the spelling used in the REPL, where `save <file>` saves the session instead
+            "SAVE" if self.is_word(1, "SNAPSHOT") && self.tokens.len() > 2 => Ok(Command::Save { path: self.path(2)? }),
+            "SAVE" => Ok(Command::Save { path: self.path(1)? }),
+            "LOAD" => Ok(Command::Load { path: self.path(1)? }),
+            "EXIT" => {
+                self.expect_end(1)?;
+                Ok(Command::Exit)
+            }
+            _ => Err(self.error_at(0, format!("unknown command '{}'", keyword))),
+        }
+    }
+
+    /// Parses the token at `index` as a decimal number
+    fn number(&self, index: usize, what: &str) -> Result<usize, ParseError> {
+        match self.tokens.get(index).map(|token| &token.kind) {
+            Some(TokenKind::Word(word)) => {
+                word.parse::<usize>().map_err(|_| self.error_at(index, format!("invalid {} '{}'", what, word)))
+            }
+            Some(_) => Err(self.error_at(index, format!("expected a {}", what))),
+            None => Err(self.error_at_end(format!("missing {}", what))),
+        }
+    }
+
+    /// Parses the token at `index` as a heap address, in decimal or in hex with a 0x prefix
+    fn address(&self, index: usize) -> Result<usize, ParseError> {
+        match self.tokens.get(index).map(|token| &token.kind) {
+            Some(TokenKind::Word(word)) => parse_number(word).ok_or_else(|| self.error_at(index, format!("invalid address '{}'", word))),
+            Some(_) => Err(self.error_at(index, "expected an address")),
+            None => Err(self.error_at_end("missing address")),
+        }
+    }
+
+    /// Parses the tokens from `index` on as byte values, in decimal or in hex with a 0x prefix
+    fn byte_values(&self, index: usize) -> Result<Vec<u8>, ParseError> {
+        if index >= self.tokens.len() {
+            return Err(self.error_at_end("missing bytes"));
+        }
+        (index..self.tokens.len())
+            .map(|i| match &self.tokens[i].kind {
+                TokenKind::Word(word) => parse_number(word)
+                    .and_then(|value| u8::try_from(value).ok())
+                    .ok_or_else(|| self.error_at(i, format!("invalid byte '{}'", word))),
+                _ => Err(self.error_at(i, "expected a byte value")),
+            })
+            .collect()
+    }
+
+    /// Returns true if the token at `index` is the given keyword, in any case
+    fn is_word(&self, index: usize, keyword: &str) -> bool {
+        matches!(self.tokens.get(index).map(|token| &token.kind), Some(TokenKind::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Parses the optional display format at `index`, which must be the last token
+    fn format(&self, index: usize) -> Result<Option<DataFormat>, ParseError> {
+        let format = match self.tokens.get(index).map(|token| &token.kind) {
+            None => return Ok(None),
+            Some(TokenKind::Word(word)) => parse_format(word),
+            Some(_) => None,
+        };
+        let format = format.ok_or_else(|| self.error_at(index, "expected a display format (TEXT, HEX or JSON)"))?;
+        self.expect_end(index + 1)?;
+        Ok(Some(format))
+    }
+
+    /// Parses the tokens from `index` on as the data of a command
+    /// The data is either a single string literal or the raw text of the remaining words,
+    /// decoded by `parse_data` so that hex and base64 forms are understood.
+    fn payload(&self, index: usize) -> Result<Vec<u8>, ParseError> {
+        let tokens = &self.tokens[index.min(self.tokens.len())..];
+        if tokens.is_empty() {
+            return Err(self.error_at_end("missing data"));
+        }
+        if tokens.len() > 1
+            && let Some(position) = tokens.iter().position(|token| matches!(token.kind, TokenKind::Str(_)))
+        {
+            return Err(self.error_at(index + position, "string literal must be the only data"));
+        }
+        let text = &self.line[tokens[0].start..tokens[tokens.len() - 1].end];
+        match parse_data(text) {
+            Ok(data) if data.is_empty() => Err(self.error_at(index, "data must not be empty")),
+            Ok(data) => Ok(data),
+            Err(e) => Err(self.error_at(index, e)),
+        }
+    }
+
+    /// Parses a PEEK, POKE or WRITE statement, where STRICT may come before the address
+    fn raw_access(&self, keyword: &str) -> Result<Command, ParseError> {
+        let strict = self.is_word(1, "STRICT");
+        let index = if strict { 2 } else { 1 };
+        let address = self.address(index)?;
+        match keyword {
+            "PEEK" => {
+                let len = self.number(index + 1, "length")?;
+                self.expect_end(index + 2)?;
+                Ok(Command::Peek { address, len, strict })
+            }
+            "POKE" => Ok(Command::Poke { address, bytes: self.byte_values(index + 1)?, strict }),
+            _ => Ok(Command::Write { address, data: self.payload(index + 1)?, strict }),
+        }
+    }
+
+    /// Parses an ARENA statement, whose second token selects the operation
+    fn arena(&self) -> Result<Command, ParseError> {
+        let operation = match self.tokens.get(1).map(|token| &token.kind) {
+            Some(TokenKind::Word(word)) => word.to_uppercase(),
+            Some(_) => return Err(self.error_at(1, "expected INSERT, MARK or RESET")),
+            None => return Err(self.error_at_end("missing arena operation (INSERT, MARK or RESET)")),
+        };
+        match operation.as_str() {
+            "INSERT" => Ok(Command::ArenaInsert { size: self.number(2, "size")?, data: self.payload(3)? }),
+            "MARK" => {
+                self.expect_end(2)?;
+                Ok(Command::ArenaMark)
+            }
+            "RESET" => {
+                let mark = self.number(2, "mark")?;
+                self.expect_end(3)?;
+                Ok(Command::ArenaReset { mark })
+            }
+            _ => Err(self.error_at(1, format!("unknown arena operation '{}'", operation))),
+        }
+    }
+
+    /// Parses a CACHE statement, whose second token selects the operation
+    fn cache(&self) -> Result<Command, ParseError> {
+        let operation = match self.tokens.get(1).map(|token| &token.kind) {
+            Some(TokenKind::Word(word)) => word.to_uppercase(),
+            Some(_) => return Err(self.error_at(1, "expected CREATE, ALLOC or FREE")),
+            None => return Err(self.error_at_end("missing cache operation (CREATE, ALLOC or FREE)")),
+        };
+        let name = self.word(2, "cache name")?;
+        let command = match operation.as_str() {
+            "CREATE" => Command::CacheCreate { name, object_size: self.number(3, "object size")? },
+            "FREE" => Command::CacheFree { name, id: self.number(3, "object ID")? },
+            "ALLOC" => Command::CacheAlloc { name },
+            _ => return Err(self.error_at(1, format!("unknown cache operation '{}'", operation))),
+        };
+        self.expect_end(if operation == "ALLOC" { 3 } else { 4 })?;
+        Ok(command)
+    }
+
+    /// Parses the token at `index` as a name, which must be a plain word
+    fn word(&self, index: usize, what: &str) -> Result<String, ParseError> {
+        match self.tokens.get(index).map(|token| &token.kind) {
+            Some(TokenKind::Word(word)) => Ok(word.clone()),
+            Some(_) => Err(self.error_at(index, format!("expected a {}", what))),
+            None => Err(self.error_at_end(format!("missing {}", what))),
+        }
+    }
+
+    /// Parses the token at `index` as the name of an allocation policy, which must be the last token
+    fn policy(&self, index: usize) -> Result<String, ParseError> {
+        let name = match self.tokens.get(index).map(|token| &token.kind) {
+            Some(TokenKind::Word(word)) => word.to_lowercase(),
+            Some(_) => return Err(self.error_at(index, "expected a policy name")),
+            None => return Err(self.error_at_end("missing policy name")),
+        };
+        if !POLICY_NAMES.contains(&name.as_str()) {
+            return Err(self.error_at(index, format!("unknown policy '{}' (expected {})", name, POLICY_NAMES.join(", "))));
+        }
+        self.expect_end(index + 1)?;
+        Ok(name)
+    }
+
+    /// Parses the token at `index` as a file path, which must be the last token
+    /// Paths holding spaces or semicolons can be written as string literals
+    fn path(&self, index: usize) -> Result<String, ParseError> {
+        let path = match self.tokens.get(index).map(|token| &token.kind) {
+            Some(TokenKind::Word(path)) | Some(TokenKind::Str(path)) => path.clone(),
+            _ => return Err(self.error_at_end("missing file path")),
+        };
+        self.expect_end(index + 1)?;
+        Ok(path)
+    }
+
+    /// Checks that the statement has no tokens from `index` on
+    fn expect_end(&self, index: usize) -> Result<(), ParseError> {
+        if index < self.tokens.len() {
+            Err(self.error_at(index, format!("unexpected '{}'", &self.line[self.tokens[index].start..self.tokens[index].end])))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn error_at(&self, index: usize, message: impl Into<String>) -> ParseError {
+        ParseError::new(self.line_number, self.line, self.tokens[index].start, message)
+    }
+
+    fn error_at_end(&self, message: impl Into<String>) -> ParseError {
+        ParseError::new(self.line_number, self.line, self.tokens[self.tokens.len() - 1].end, message)
+    }
+}
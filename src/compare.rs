@@ -0,0 +1,140 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::command::Command;
+use crate::memory_manager::MemoryManager;
+use crate::memory_manager::config::MemoryConfig;
+use crate::memory_manager::error::MemoryError;
+use crate::workload::MallocTrace;
+
+/// Workload is what a comparison runs against every policy.
+#[derive(Clone, Debug)]
+pub enum Workload {
+    /// The commands of a .cmmd script, run until the first EXIT.
+    Script { name: String, commands: Vec<Command> },
+    /// A malloc-lab trace.
+    MallocTrace(MallocTrace),
+}
+
+/// PolicyResult describes how one policy coped with the workload of a comparison.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyResult {
+    pub policy: &'static str,
+    pub failed_allocations: usize,
+    pub peak_allocated_bytes: usize,
+    pub high_water_mark: usize,
+    pub external_fragmentation: f64, // At the end of the workload
+    pub average_search_steps: f64,   // Search steps of the policy per allocation, failed or not
+    pub elapsed: Duration,
+}
+
+/// Comparison is the result of running the same workload against several allocation policies,
+/// each on a new manager with the same heap configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub workload: String,
+    pub results: Vec<PolicyResult>,
+}
+
+/// Implement Workload enum
+impl Workload {
+    /// Returns the name of the script or trace
+    pub fn name(&self) -> &str {
+        match self {
+            Workload::Script { name, .. } => name,
+            Workload::MallocTrace(trace) => &trace.name,
+        }
+    }
+
+    /// Function to run the workload on a manager
+    fn run(&self, manager: &mut MemoryManager) {
+        match self {
+            Workload::Script { commands, .. } => {
+                for command in commands {
+                    if manager.execute(command).is_exit() {
+                        break;
+                    }
+                }
+            }
+            Workload::MallocTrace(trace) => {
+                trace.run(manager);
+            }
+        }
+    }
+}
+
+/// Implement Comparison struct
+impl Comparison {
+    /// Function to run the workload against each of the named policies
+    /// Only the workload itself is timed, not the creation of the manager or the statistics
+    pub fn run(config: MemoryConfig, workload: &Workload, policies: &[&str]) -> Result<Comparison, MemoryError> {
+        let mut results = Vec::new();
+        for &name in policies {
+            let mut manager = MemoryManager::with_policy_name(config, name)?;
+            let started = Instant::now();
+            workload.run(&mut manager);
+            let elapsed = started.elapsed();
+
+            let stats = manager.stats();
+            let counters = manager.policy_counters();
+            let attempts = counters.allocations + counters.failed_allocations;
+            results.push(PolicyResult {
+                policy: manager.policy_name(),
+                failed_allocations: counters.failed_allocations,
+                peak_allocated_bytes: stats.usage.peak_allocated_bytes,
+                high_water_mark: stats.usage.high_water_mark,
+                external_fragmentation: stats.external_fragmentation,
+                average_search_steps: if attempts == 0 { 0.0 } else { counters.search_steps as f64 / attempts as f64 },
+                elapsed,
+            });
+        }
+        Ok(Comparison { workload: workload.name().to_string(), results })
+    }
+
+    /// Function to write the results as CSV, with a header line and one line per policy
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "policy,failed_allocations,peak_allocated_bytes,high_water_mark,external_fragmentation,average_search_steps,wall_clock_ms\n",
+        );
+        for result in &self.results {
+            csv.push_str(&format!(
+                "{},{},{},{},{:.4},{:.2},{:.3}\n",
+                result.policy,
+                result.failed_allocations,
+                result.peak_allocated_bytes,
+                result.high_water_mark,
+                result.external_fragmentation,
+                result.average_search_steps,
+                result.elapsed.as_secs_f64() * 1000.0
+            ));
+        }
+        csv
+    }
+}
+
+/// Implement Display for Comparison
+/// The results are shown as a table with one row per policy
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Comparison of {}:", self.workload)?;
+        write!(
+            f,
+            "{:<14}{:>8}{:>12}{:>12}{:>10}{:>12}{:>12}",
+            "Policy", "Failed", "Peak bytes", "High-water", "Ext frag", "Avg steps", "Time (ms)"
+        )?;
+        for result in &self.results {
+            write!(
+                f,
+                "\n{:<14}{:>8}{:>12}{:>12}{:>9.1}%{:>12.2}{:>12.3}",
+                result.policy,
+                result.failed_allocations,
+                result.peak_allocated_bytes,
+                format!("0x{:04X}", result.high_water_mark),
+                result.external_fragmentation * 100.0,
+                result.average_search_steps,
+                result.elapsed.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,18 +1,157 @@
-use std::{env, fs::File, io::{BufRead, BufReader}};
-use std::collections::HashMap;
-
+pub mod command;
+pub mod compare;
+pub mod json;
+pub mod line_editor;
 pub mod memory_manager;
+pub mod repl;
+pub mod workload;
+
+/// DataFormat selects how block data is displayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DataFormat {
+    /// The data is shown as text, with invalid UTF-8 replaced.
+    #[default]
+    Text,
+    /// The data is shown as hex bytes in the form accepted by `parse_data`.
+    Hex,
+    /// Blocks are shown as JSON objects, with the data as a text and a hex string.
+    Json,
+}
 
 /// Function to parse data from the command line input file
-pub fn parse_data(data: &str) -> Vec<u8> {
+/// The data can be written in one of these forms:
+/// - `0x48 65 6C` or `0x48656C`: hex bytes, optionally separated by whitespace
+/// - `base64:SGVs`: base64 encoded bytes
+/// - `"Hel\x6C"`: a quoted string with `\n`, `\r`, `\t`, `\0`, `\\`, `\"` and `\xHH` escapes
+/// - anything else is taken as raw text
+pub fn parse_data(data: &str) -> Result<Vec<u8>, String> {
     if data.starts_with("0x") || data.starts_with("0X") {
-        // Explicitly specify the type to collect to Vec<u8>
-        data[2..]
-            .split_whitespace()
-            .map(|b| u8::from_str_radix(b, 16).expect("Failed to parse hex"))
-            .collect::<Vec<u8>>()
+        parse_hex(&data[2..])
+    } else if let Some(encoded) = data.strip_prefix("base64:") {
+        parse_base64(encoded)
+    } else if data.len() >= 2 && data.starts_with('"') && data.ends_with('"') {
+        parse_escaped(&data[1..data.len() - 1])
     } else {
-        data.as_bytes().to_vec()
+        Ok(data.as_bytes().to_vec())
+    }
+}
+
+/// Function to parse whitespace separated groups of hex digits into bytes
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for group in hex.split_whitespace() {
+        if group.len() % 2 != 0 {
+            return Err(format!("hex group '{}' has an odd number of digits", group));
+        }
+        for i in (0..group.len()).step_by(2) {
+            let byte = group
+                .get(i..i + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| format!("invalid hex byte in '{}'", group))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Function to decode standard base64, with or without padding
+fn parse_base64(encoded: &str) -> Result<Vec<u8>, String> {
+    let encoded = encoded.trim().trim_end_matches('=');
+    let mut bytes = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in encoded.chars() {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            _ => return Err(format!("invalid base64 character '{}'", c)),
+        };
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    if bits >= 6 {
+        return Err("truncated base64 data".to_string());
+    }
+    Ok(bytes)
+}
+
+/// Function to resolve the escapes of a quoted string
+fn parse_escaped(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('"') => bytes.push(b'"'),
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&digits, 16)
+                    .ok()
+                    .filter(|_| digits.len() == 2)
+                    .ok_or_else(|| format!("invalid escape '\\x{}'", digits))?;
+                bytes.push(byte);
+            }
+            Some(other) => return Err(format!("unknown escape '\\{}'", other)),
+            None => return Err("string ends with a lone '\\'".to_string()),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Function to format data for display
+/// Text is shown as-is, while hex is shown in a form that `parse_data` reads back
+pub fn format_data(data: &[u8], format: DataFormat) -> String {
+    match format {
+        DataFormat::Text => String::from_utf8_lossy(data).into_owned(),
+        DataFormat::Hex => {
+            let bytes: Vec<String> = data.iter().map(|b| format!("{:02X}", b)).collect();
+            format!("0x{}", bytes.join(" "))
+        }
+        DataFormat::Json => json::JsonValue::from(String::from_utf8_lossy(data).into_owned()).to_string(),
+    }
+}
+
+/// Function to write data as a literal that `parse_data` reads back unchanged
+/// Valid UTF-8 becomes a quoted string with escapes, anything else becomes hex
+pub fn quote_data(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) => {
+            let mut quoted = String::from("\"");
+            for c in text.chars() {
+                match c {
+                    '\n' => quoted.push_str("\\n"),
+                    '\r' => quoted.push_str("\\r"),
+                    '\t' => quoted.push_str("\\t"),
+                    '\\' => quoted.push_str("\\\\"),
+                    '"' => quoted.push_str("\\\""),
+                    c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02X}", c as u32)),
+                    c => quoted.push(c),
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+        Err(_) => format_data(data, DataFormat::Hex),
     }
 }
 
@@ -26,4 +165,39 @@ pub fn nearest_power_of_two(size: usize) -> usize {
     n |= n >> 16;
     n += 1;
     n
-}
\ No newline at end of file
+}
+
+/// Function to parse a whole number of a trace file or workload option
+pub fn parse_count(text: &str) -> Result<usize, String> {
+    text.trim().parse().map_err(|_| format!("invalid number '{}'", text))
+}
+
+/// Function to parse a number of a trace line, which may be missing
+/// Errors name the line, as trace files are read a line at a time
+pub fn parse_trace_number(text: Option<&str>, line: usize) -> Result<usize, String> {
+    let text = text.ok_or_else(|| format!("line {}: missing number", line))?;
+    parse_count(text).map_err(|e| format!("line {}: {}", line, e))
+}
+
+/// Function to parse a size given on the command line
+/// Accepts plain byte counts as well as K/KiB and M/MiB suffixes, e.g. "4096", "4K" or "2MiB"
+pub fn parse_size(size: &str) -> Result<usize, String> {
+    let size = size.trim();
+    let (digits, multiplier) = match size.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => {
+            let multiplier = match size[index..].to_uppercase().as_str() {
+                "K" | "KB" | "KIB" => 1024,
+                "M" | "MB" | "MIB" => 1024 * 1024,
+                "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+                suffix => return Err(format!("Unknown size suffix '{}' in '{}'", suffix, size)),
+            };
+            (&size[..index], multiplier)
+        }
+        None => (size, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid size '{}'", size))
+}
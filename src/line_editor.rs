@@ -0,0 +1,232 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+/// Key is one key press decoded from the bytes sent by a terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    KillLine,   // Ctrl-U
+    Interrupt,  // Ctrl-C
+    EndOfInput, // Ctrl-D
+    Ignored,
+}
+
+/// LineEditor reads one line from a terminal in raw mode.
+/// Left/Right, Home/End (or Ctrl-A/Ctrl-E), Backspace/Delete and Ctrl-U edit the line,
+/// Up/Down walk through the history, Ctrl-C drops the line and Ctrl-D on an empty line ends the input.
+pub struct LineEditor<'a> {
+    prompt: &'a str,
+    history: &'a [String],
+    buffer: Vec<char>,
+    cursor: usize,
+    history_index: usize, // Index of the history entry shown, history.len() for the line being typed
+    draft: Vec<char>,     // Line being typed, kept while the history is shown
+}
+
+/// Implement LineEditor struct
+impl<'a> LineEditor<'a> {
+    /// Creates an editor for a new line, recalling entries from `history`
+    pub fn new(prompt: &'a str, history: &'a [String]) -> Self {
+        LineEditor {
+            prompt,
+            history,
+            buffer: Vec::new(),
+            cursor: 0,
+            history_index: history.len(),
+            draft: Vec::new(),
+        }
+    }
+
+    /// Function to read keys until Enter is pressed, echoing the line to `output` as it is edited
+    /// Returns None when the input ends
+    pub fn read_line<R: Read, W: Write>(mut self, input: &mut R, output: &mut W) -> io::Result<Option<String>> {
+        self.redraw(output)?;
+        loop {
+            let key = match read_key(input)? {
+                Some(key) => key,
+                None if self.buffer.is_empty() => return Ok(None),
+                None => Key::Enter,
+            };
+            match key {
+                Key::Char(c) => {
+                    self.buffer.insert(self.cursor, c);
+                    self.cursor += 1;
+                }
+                Key::Enter => {
+                    writeln!(output)?;
+                    return Ok(Some(self.buffer.iter().collect()));
+                }
+                Key::Backspace if self.cursor > 0 => {
+                    self.cursor -= 1;
+                    self.buffer.remove(self.cursor);
+                }
+                Key::Delete if self.cursor < self.buffer.len() => {
+                    self.buffer.remove(self.cursor);
+                }
+                Key::Left => self.cursor = self.cursor.saturating_sub(1),
+                Key::Right => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+                Key::Home => self.cursor = 0,
+                Key::End => self.cursor = self.buffer.len(),
+                Key::Up if self.history_index > 0 => self.recall(self.history_index - 1),
+                Key::Down if self.history_index < self.history.len() => self.recall(self.history_index + 1),
+                Key::KillLine => {
+                    self.buffer.drain(..self.cursor);
+                    self.cursor = 0;
+                }
+                Key::Interrupt => {
+                    writeln!(output, "^C")?;
+                    self.buffer.clear();
+                    self.cursor = 0;
+                    self.history_index = self.history.len();
+                }
+                Key::EndOfInput if self.buffer.is_empty() => {
+                    writeln!(output)?;
+                    return Ok(None);
+                }
+                _ => continue,
+            }
+            self.redraw(output)?;
+        }
+    }
+
+    /// Function to show a history entry, or the draft once the end of the history is reached
+    fn recall(&mut self, index: usize) {
+        if self.history_index == self.history.len() {
+            self.draft = self.buffer.clone();
+        }
+        self.history_index = index;
+        self.buffer = match self.history.get(index) {
+            Some(entry) => entry.chars().collect(),
+            None => self.draft.clone(),
+        };
+        self.cursor = self.buffer.len();
+    }
+
+    /// Function to rewrite the current line and put the terminal cursor back where the editor cursor is
+    fn redraw<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let line: String = self.buffer.iter().collect();
+        write!(output, "\r{}{}\x1b[K", self.prompt, line)?;
+        let behind = self.buffer.len() - self.cursor;
+        if behind > 0 {
+            write!(output, "\x1b[{}D", behind)?;
+        }
+        output.flush()
+    }
+}
+
+/// Function to read one byte, or None at the end of the input
+fn read_byte<R: Read>(input: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Function to decode the next key press, or None at the end of the input
+/// Escape sequences of the arrow, Home, End and Delete keys are recognised, other ones are ignored
+fn read_key<R: Read>(input: &mut R) -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(None);
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x05 => Key::End,
+        0x15 => Key::KillLine,
+        0x03 => Key::Interrupt,
+        0x04 => Key::EndOfInput,
+        0x1b => read_escape(input)?,
+        byte if byte < 0x20 => Key::Ignored,
+        byte if byte < 0x80 => Key::Char(byte as char),
+        byte => {
+            // Multi-byte UTF-8 character: the leading byte tells how many bytes follow
+            let len = byte.leading_ones() as usize;
+            let mut bytes = vec![byte];
+            for _ in 1..len.min(4) {
+                match read_byte(input)? {
+                    Some(next) => bytes.push(next),
+                    None => break,
+                }
+            }
+            String::from_utf8(bytes).ok().and_then(|s| s.chars().next()).map_or(Key::Ignored, Key::Char)
+        }
+    };
+    Ok(Some(key))
+}
+
+/// Function to decode the rest of an escape sequence, after the ESC byte
+fn read_escape<R: Read>(input: &mut R) -> io::Result<Key> {
+    let Some(kind) = read_byte(input)? else {
+        return Ok(Key::Ignored);
+    };
+    if kind != b'[' && kind != b'O' {
+        return Ok(Key::Ignored);
+    }
+    // Parameters are digits and ';', and the sequence ends with its final byte
+    let mut parameter = String::new();
+    let last = loop {
+        match read_byte(input)? {
+            Some(byte) if byte.is_ascii_digit() || byte == b';' => parameter.push(byte as char),
+            Some(byte) => break byte,
+            None => return Ok(Key::Ignored),
+        }
+    };
+    Ok(match (last, parameter.as_str()) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) => Key::Right,
+        (b'D', _) => Key::Left,
+        (b'H', _) | (b'~', "1" | "7") => Key::Home,
+        (b'F', _) | (b'~', "4" | "8") => Key::End,
+        (b'~', "3") => Key::Delete,
+        _ => Key::Ignored,
+    })
+}
+
+/// RawMode switches the terminal on stdin to raw input until it is dropped.
+/// Keys are then read one at a time and are not echoed, so the line editor can draw the line itself.
+/// The terminal is driven with `stty`, so this only works where `stty` is available.
+pub struct RawMode {
+    saved: String, // Settings printed by `stty -g`, restored on drop
+}
+
+/// Implement RawMode struct
+impl RawMode {
+    /// Function to put the terminal in raw mode, saving its current settings
+    pub fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "-ixon", "min", "1"])?;
+        Ok(RawMode { saved: saved.trim().to_string() })
+    }
+}
+
+/// Implement Drop for RawMode
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[self.saved.as_str()]);
+    }
+}
+
+/// Function to run `stty` on the terminal of stdin and return what it prints
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
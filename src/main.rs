@@ -1,25 +1,430 @@
+use systems_project::command::{Command, parse_format, parse_script};
+use systems_project::compare::{Comparison, Workload};
 use systems_project::memory_manager::MemoryManager;
+use systems_project::memory_manager::config::MemoryConfig;
+use systems_project::memory_manager::policy::POLICY_NAMES;
+use systems_project::memory_manager::shadow::ShadowConfig;
+use systems_project::memory_manager::sink::WriterSink;
+use systems_project::memory_manager::trace::Trace;
+use systems_project::{DataFormat, parse_size};
+use systems_project::line_editor::RawMode;
+use systems_project::repl::Repl;
+use systems_project::workload::{Distribution, MallocTrace, OperationMix, WorkloadSpec};
 use std::env;
-use std::fs::File;
-use std::io::{BufRead, BufReader};  
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::process;
 
 
+/// Function to print the usage message
+fn print_usage(program: &str) {
+    println!("Usage: {} [options] [path_to_cmmd_file]", program);
+    println!("       {} generate [options]", program);
+    println!("       {} compare [options] (path_to_cmmd_file | --malloc-trace <file>)", program);
+    println!("Without a command file, an interactive session is started.");
+    println!("Options:");
+    println!("  --heap-size <size>    Total heap size, a power of two (default 64K)");
+    println!("  --min-block <size>    Minimum block size, a power of two (default 1)");
+    println!("  --max-order <order>   Largest block is min-block << max-order (default: whole heap)");
+    println!("  --policy <name>       Allocation policy: {} (default buddy)", POLICY_NAMES.join(", "));
+    println!("  --format <format>     Default output of READ and DUMP: text, hex or json (default text)");
+    println!("  --check               Only check the command file for errors, without running it");
+    println!("  --interactive         Start an interactive session, after running the command file if one is given");
+    println!("  --verify-heap         Check the invariants of the heap after every command");
+    println!("  --shadow              Track the state of every heap byte and check raw accesses like AddressSanitizer");
+    println!("  --redzone <size>      Poisoned bytes after every block, implies --shadow (default 16)");
+    println!("  --quarantine <size>   Freed bytes kept from reuse, implies --shadow (default 4K)");
+    println!("  --trace <file>        Record every operation of the command file that places or frees blocks into a trace");
+    println!("  --replay <trace>      Run a recorded trace again and check that every block lands at the same address");
+    println!("  --malloc-trace <file> Run a malloc-lab trace (a/f/r requests) and report how the policy coped, may be repeated");
+    println!("Generate options, besides the heap options above:");
+    println!("  --seed <n>            Seed of the random numbers (default 1)");
+    println!("  --ops <n>             Number of commands to generate (default 1000)");
+    println!("  --sizes <dist>        Block sizes: uniform:<min>-<max>, exponential:<mean>, bimodal:<small>,<large>,<share>");
+    println!("                        or pow2:<min>-<max> (default uniform:16-256)");
+    println!("  --lifetimes <dist>    Steps each block lives, as a distribution or forever (default exponential:100)");
+    println!("  --mix <mix>           Weights of the commands (default insert=50,update=20,read=20,delete=10)");
+    println!("  --output <file>       Write the commands to a .cmmd script, or to stdout for -, instead of only reporting");
+    println!("Compare options, besides the heap size options above:");
+    println!("  --policies <names>    Comma-separated policies to run the workload against (default: all)");
+    println!("  --csv                 Print the results as CSV instead of a table");
+}
+
+/// Function to apply one of the options choosing the heap configuration and policy
+/// The flag must be one of HEAP_OPTIONS
+fn apply_heap_option(config: &mut MemoryConfig, policy: &mut String, flag: &str, value: &str) -> Result<(), String> {
+    match flag {
+        "--heap-size" => *config = config.with_heap_size(parse_size(value)?),
+        "--min-block" => *config = config.with_min_block_size(parse_size(value)?),
+        "--max-order" => {
+            let order = value.parse::<usize>().map_err(|_| format!("Invalid order '{}'", value))?;
+            *config = config.with_max_order(order);
+        }
+        _ => *policy = value.to_lowercase(),
+    }
+    Ok(())
+}
+
+/// Options of the heap that both modes accept
+const HEAP_OPTIONS: [&str; 4] = ["--heap-size", "--min-block", "--max-order", "--policy"];
+
+/// Options is the parsed command line
+struct Options {
+    config: MemoryConfig,
+    policy: String,
+    format: DataFormat,
+    check_only: bool,
+    interactive: bool,
+    verify_heap: bool,
+    shadow: Option<ShadowConfig>,
+    trace_path: Option<String>,
+    replay_path: Option<String>,
+    malloc_traces: Vec<String>,
+    file_path: Option<String>,
+}
+
+/// Function to parse the command line into a heap configuration and the path of the command file
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut config = MemoryConfig::new();
+    let mut policy = "buddy".to_string();
+    let mut format = DataFormat::Text;
+    let mut check_only = false;
+    let mut interactive = false;
+    let mut verify_heap = false;
+    let mut shadow: Option<ShadowConfig> = None;
+    let mut trace_path = None;
+    let mut replay_path = None;
+    let mut malloc_traces = Vec::new();
+    let mut file_path = None;
+    let mut iter = args.iter().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let mut value = |flag: &str| iter.next().cloned().ok_or_else(|| format!("Missing value for {}", flag));
+        match arg.as_str() {
+            flag if HEAP_OPTIONS.contains(&flag) => apply_heap_option(&mut config, &mut policy, flag, &value(arg)?)?,
+            "--format" => {
+                let name = value(arg)?;
+                format = parse_format(&name).ok_or_else(|| format!("Unknown format '{}'", name))?;
+            }
+            "--check" => check_only = true,
+            "--interactive" => interactive = true,
+            "--verify-heap" => verify_heap = true,
+            "--shadow" => shadow = Some(shadow.unwrap_or_default()),
+            "--redzone" => shadow = Some(shadow.unwrap_or_default().with_redzone(parse_size(&value(arg)?)?)),
+            "--quarantine" => shadow = Some(shadow.unwrap_or_default().with_quarantine(parse_size(&value(arg)?)?)),
+            "--trace" => trace_path = Some(value(arg)?),
+            "--replay" => replay_path = Some(value(arg)?),
+            "--malloc-trace" => malloc_traces.push(value(arg)?),
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+            path if file_path.is_none() => file_path = Some(path.to_string()),
+            extra => return Err(format!("Unexpected argument {}", extra)),
+        }
+    }
+
+    if check_only && file_path.is_none() {
+        return Err("--check needs a command file".to_string());
+    }
+    if trace_path.is_some() && file_path.is_none() {
+        return Err("--trace needs a command file".to_string());
+    }
+    if replay_path.is_some() && file_path.is_some() {
+        return Err("--replay cannot be combined with a command file".to_string());
+    }
+    if !malloc_traces.is_empty() && file_path.is_some() {
+        return Err("--malloc-trace cannot be combined with a command file".to_string());
+    }
+    let interactive = interactive || (file_path.is_none() && replay_path.is_none() && malloc_traces.is_empty());
+    Ok(Options {
+        config,
+        policy,
+        format,
+        check_only,
+        interactive,
+        verify_heap,
+        shadow,
+        trace_path,
+        replay_path,
+        malloc_traces,
+        file_path,
+    })
+}
+
+/// GenerateOptions is the parsed command line of the generate subcommand
+struct GenerateOptions {
+    config: MemoryConfig,
+    policy: String,
+    spec: WorkloadSpec,
+    output: Option<String>,
+}
+
+/// Function to parse the command line of the generate subcommand
+fn parse_generate_args(args: &[String]) -> Result<GenerateOptions, String> {
+    let mut config = MemoryConfig::new();
+    let mut policy = "buddy".to_string();
+    let mut spec = WorkloadSpec::default();
+    let mut output = None;
+    let mut iter = args.iter().skip(2);
+
+    while let Some(arg) = iter.next() {
+        let value = iter.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+        let number = || value.parse::<u64>().map_err(|_| format!("Invalid number '{}' for {}", value, arg));
+        match arg.as_str() {
+            flag if HEAP_OPTIONS.contains(&flag) => apply_heap_option(&mut config, &mut policy, flag, value)?,
+            "--seed" => spec.seed = number()?,
+            "--ops" => spec.operations = number()? as usize,
+            "--sizes" => spec.sizes = Distribution::parse(value).map_err(|e| format!("--sizes: {}", e))?,
+            "--lifetimes" if value == "forever" => spec.lifetimes = None,
+            "--lifetimes" => spec.lifetimes = Some(Distribution::parse(value).map_err(|e| format!("--lifetimes: {}", e))?),
+            "--mix" => spec.mix = OperationMix::parse(value).map_err(|e| format!("--mix: {}", e))?,
+            "--output" => output = Some(value.clone()),
+            other => return Err(format!("Unknown option {}", other)),
+        }
+    }
+    Ok(GenerateOptions { config, policy, spec, output })
+}
+
+/// Function to generate a workload, writing its commands to a script or only reporting how the
+/// policy coped with it
+fn generate(options: GenerateOptions) {
+    let mut manager = match MemoryManager::with_policy_name(options.config, &options.policy) {
+        Ok(manager) => manager,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(2);
+        }
+    };
+    let mut script = String::new();
+    let report = options.spec.run(&mut manager, |command| {
+        if options.output.is_some() {
+            script.push_str(&format!("{}\n", command));
+        }
+    });
+
+    match options.output.as_deref() {
+        Some("-") => print!("{}", script),
+        Some(path) => {
+            if let Err(e) = fs::write(path, &script) {
+                println!("Unable to write the script to {}: {}", path, e);
+                process::exit(1);
+            }
+            println!("Wrote {} command(s) to {}", report.operations, path);
+            println!("{}", report);
+        }
+        None => println!("{}", report),
+    }
+}
+
+/// CompareOptions is the parsed command line of the compare subcommand
+struct CompareOptions {
+    config: MemoryConfig,
+    policies: Vec<String>,
+    csv: bool,
+    workload: Workload,
+}
+
+/// Function to parse the command line of the compare subcommand, reading the workload it names
+fn parse_compare_args(args: &[String]) -> Result<CompareOptions, String> {
+    let mut config = MemoryConfig::new();
+    let mut policies: Vec<String> = POLICY_NAMES.iter().map(|name| name.to_string()).collect();
+    let mut csv = false;
+    let mut workload = None;
+    let mut iter = args.iter().skip(2);
+
+    while let Some(arg) = iter.next() {
+        let mut value = |flag: &str| iter.next().cloned().ok_or_else(|| format!("Missing value for {}", flag));
+        match arg.as_str() {
+            flag if HEAP_OPTIONS.contains(&flag) && flag != "--policy" => {
+                apply_heap_option(&mut config, &mut String::new(), flag, &value(arg)?)?
+            }
+            "--policies" => policies = value(arg)?.split(',').map(|name| name.trim().to_lowercase()).collect(),
+            "--csv" => csv = true,
+            "--malloc-trace" if workload.is_none() => {
+                let path = value(arg)?;
+                let source = fs::read_to_string(&path).map_err(|e| format!("Unable to open {}: {}", path, e))?;
+                workload = Some(Workload::MallocTrace(MallocTrace::parse(&path, &source).map_err(|e| format!("{}: {}", path, e))?));
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+            path if workload.is_none() => {
+                let source = fs::read_to_string(path).map_err(|e| format!("Unable to open {}: {}", path, e))?;
+                let statements = parse_script(path, &source).map_err(|errors| {
+                    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+                })?;
+                let commands = statements.into_iter().map(|statement| statement.command).collect();
+                workload = Some(Workload::Script { name: path.to_string(), commands });
+            }
+            extra => return Err(format!("Unexpected argument {}", extra)),
+        }
+    }
+    let workload = workload.ok_or("compare needs a command file or a malloc trace")?;
+    Ok(CompareOptions { config, policies, csv, workload })
+}
+
+/// Function to run the workload of the compare subcommand against each policy and print the results
+fn compare(options: CompareOptions) {
+    let policies: Vec<&str> = options.policies.iter().map(String::as_str).collect();
+    match Comparison::run(options.config, &options.workload, &policies) {
+        Ok(comparison) if options.csv => print!("{}", comparison.to_csv()),
+        Ok(comparison) => println!("{}", comparison),
+        Err(e) => {
+            println!("{}", e);
+            process::exit(2);
+        }
+    }
+}
+
+/// Function to run a recorded trace again, exiting with an error if any result differs
+/// The heap configuration and policy come from the trace header
+fn replay(path: &str) {
+    let source = fs::read_to_string(path).expect("Unable to open the trace");
+    let trace = match Trace::parse(&source) {
+        Ok(trace) => trace,
+        Err(e) => {
+            println!("{}: {}", path, e);
+            process::exit(2);
+        }
+    };
+    match trace.replay() {
+        Ok(events) => println!("{}: {} event(s) replayed, all results match", path, events),
+        Err(e) => {
+            println!("{}: replay differs at {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Function to run each malloc-lab trace on a new manager and print its report
+fn run_malloc_traces(options: &Options) {
+    for path in &options.malloc_traces {
+        let source = fs::read_to_string(path).expect("Unable to open the trace");
+        let trace = match MallocTrace::parse(path, &source) {
+            Ok(trace) => trace,
+            Err(e) => {
+                println!("{}: {}", path, e);
+                process::exit(2);
+            }
+        };
+        match MemoryManager::with_policy_name(options.config, &options.policy) {
+            Ok(mut manager) => println!("{}", trace.run(&mut manager)),
+            Err(e) => {
+                println!("{}", e);
+                process::exit(2);
+            }
+        }
+    }
+}
+
+/// Function to write the trace recorded by the manager to the given file
+fn write_trace(manager: &mut MemoryManager, path: &str) {
+    if let Some(trace) = manager.take_trace()
+        && let Err(e) = fs::write(path, trace.to_string())
+    {
+        println!("Unable to write the trace to {}: {}", path, e);
+        process::exit(1);
+    }
+}
+
 /// Main function to read commands from a file and execute them
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <path_to_cmmd_file>", args[0]);
+    if args.get(1).is_some_and(|arg| arg == "generate") {
+        match parse_generate_args(&args) {
+            Ok(options) => generate(options),
+            Err(e) => {
+                println!("{}", e);
+                print_usage(&args[0]);
+                process::exit(2);
+            }
+        }
+        return;
+    }
+    if args.get(1).is_some_and(|arg| arg == "compare") {
+        match parse_compare_args(&args) {
+            Ok(options) => compare(options),
+            Err(e) => {
+                println!("{}", e);
+                print_usage(&args[0]);
+                process::exit(2);
+            }
+        }
+        return;
+    }
+    let options = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(&args[0]);
+            process::exit(2);
+        }
+    };
+    if let Some(path) = &options.replay_path {
+        replay(path);
+    }
+    run_malloc_traces(&options);
+    if (options.replay_path.is_some() || !options.malloc_traces.is_empty()) && !options.interactive {
         return;
     }
 
-    let file_path = &args[1];
-    let file = File::open(file_path).expect("Unable to open the file");
-    let reader = BufReader::new(file);
+    let mut manager = match MemoryManager::with_policy_name(options.config, &options.policy) {
+        Ok(manager) => manager,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(2);
+        }
+    };
+    manager.set_default_format(options.format);
+    manager.set_verify_commands(options.verify_heap);
+    if let Some(shadow) = options.shadow {
+        manager.enable_shadow(shadow).expect("a new manager has no blocks");
+    }
 
-    let mut manager = MemoryManager::new();
+    if let Some(file_path) = &options.file_path {
+        let source = fs::read_to_string(file_path).expect("Unable to open the file");
+        let statements = match parse_script(file_path, &source) {
+            Ok(statements) => statements,
+            Err(errors) => {
+                for e in &errors {
+                    println!("{}", e);
+                }
+                println!("{} error(s) found in {}", errors.len(), file_path);
+                process::exit(1);
+            }
+        };
+        if options.check_only {
+            println!("{}: {} command(s), no errors found", file_path, statements.len());
+            return;
+        }
+
+        if options.trace_path.is_some() {
+            // A replay starts from an empty heap, so a snapshot loaded by the script could not be replayed
+            if let Some(statement) = statements.iter().find(|statement| matches!(statement.command, Command::Load { .. })) {
+                println!("{}:{}: LOAD cannot be recorded in a trace", file_path, statement.line);
+                process::exit(1);
+            }
+            manager.start_trace();
+        }
+        manager.set_sink(Box::new(WriterSink::new(io::stdout())));
+        let exited = statements.iter().any(|statement| manager.execute(&statement.command).is_exit());
+        manager.take_sink();
+        if let Some(trace_path) = &options.trace_path {
+            write_trace(&mut manager, trace_path);
+        }
+        if exited {
+            return;
+        }
+    }
 
-    for line in reader.lines() {
-        let line = line.expect("Unable to read line");
-        manager.execute_command(&line);
+    if options.interactive {
+        let mut repl = Repl::new(manager, io::stdout());
+        // Line editing needs the terminal in raw mode, piped input is read line by line
+        let raw_mode = if io::stdin().is_terminal() { RawMode::enable().ok() } else { None };
+        let result = match raw_mode {
+            Some(_) => repl.run_editing(io::stdin().lock()),
+            None => repl.run(io::stdin().lock()),
+        };
+        drop(raw_mode);
+        if let Err(e) = result {
+            println!("Error: {}", e);
+            process::exit(1);
+        }
     }
-}
\ No newline at end of file
+}
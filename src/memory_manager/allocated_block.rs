@@ -9,6 +9,7 @@ pub struct AllocatedBlock {
     pub id: usize,
     pub data_size: usize,
     pub data: Vec<u8>,
+    pub generation: u32, // Number of times the block was moved by `update_stable`
 }
 
 /// Implement AllocatedBlock struct
@@ -23,6 +24,7 @@ impl AllocatedBlock {
             id,
             data_size,
             data: vec![0; data_size],
+            generation: 0,
         }
     }
     
@@ -41,16 +43,16 @@ impl AllocatedBlock {
     }
 
     /// Returns the id field of the allocated block.
-    pub(crate) fn get_id(&self) -> usize {
+    pub fn get_id(&self) -> usize {
         self.id
     }
 
     /// Returns the data_size field of the allocated block.
-    pub(crate) fn get_data_size(&self) -> usize {
+    pub fn get_data_size(&self) -> usize {
         self.data_size
     }
 
-    pub(crate) fn set_data_size(&mut self, data_size: usize) {
+    pub fn set_data_size(&mut self, data_size: usize) {
         self.data_size = data_size;
     }
 
@@ -107,6 +109,7 @@ impl DataMemoryBlock for AllocatedBlock {
         self.data_size
     }
     fn get_end(&self) -> usize {
-        self.start + self.data_size - 1
+        // A block without data is shown as ending at its start
+        self.start + self.data_size.saturating_sub(1)
     }
 }
\ No newline at end of file
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use super::MemoryManager;
+use super::allocated_block::AllocatedBlock;
+use super::error::MemoryError;
+use super::free_block::FreeBlock;
+use super::handle::{Handle, Staleness};
+use super::trace::{TraceOp, TraceResult};
+
+/// Size of the chunks the arena takes from the heap when it runs out of room
+/// Small heaps use chunks of an eighth of the heap instead, and larger blocks get a chunk of their own size
+pub const ARENA_CHUNK_SIZE: usize = 1024;
+
+/// ArenaChunk is a region of the heap taken by the arena, filled from its start.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArenaChunk {
+    pub start: usize,
+    pub size: usize,
+    pub used: usize,
+}
+
+/// Arena is a bump allocator for short-lived data, living beside the allocation policy.
+/// It takes chunks from the policy and hands out blocks one after the other inside the last chunk.
+/// Arena blocks are ordinary allocated blocks that can be read and updated in place, but they are
+/// never freed one by one: `reset` frees every block allocated after a mark in one step, and gives
+/// the chunks that become empty back to the policy. A mark is the number of blocks in the arena.
+#[derive(Clone, Debug, Default)]
+pub struct Arena {
+    pub(crate) chunks: Vec<ArenaChunk>,
+    pub(crate) blocks: Vec<usize>, // IDs of the arena blocks, in allocation order
+}
+
+/// Implement Arena struct
+impl Arena {
+    /// Returns the chunks taken by the arena
+    pub fn get_chunks(&self) -> &[ArenaChunk] {
+        &self.chunks
+    }
+
+    /// Returns the number of blocks in the arena, which is also the current mark
+    pub fn get_block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the number of bytes handed out in all chunks
+    pub fn get_used_bytes(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.used).sum()
+    }
+
+    /// Function to work out how much of each chunk is used from the blocks of the arena
+    /// Blocks fill the chunks in order, so each chunk ends where its last block ends
+    pub(crate) fn update_usage(&mut self, allocated_blocks: &HashMap<usize, AllocatedBlock>) {
+        for chunk in &mut self.chunks {
+            chunk.used = 0;
+        }
+        for id in &self.blocks {
+            let block = &allocated_blocks[id];
+            let chunk = self.chunks.iter_mut().find(|chunk| (chunk.start..chunk.start + chunk.size).contains(&block.start));
+            if let Some(chunk) = chunk {
+                chunk.used = block.start + block.size - chunk.start;
+            }
+        }
+    }
+}
+
+/// Arena allocation of the memory manager
+impl MemoryManager {
+    /// Function to allocate a block of `size` bytes in the arena
+    /// A new chunk is taken from the allocation policy when the last chunk is full
+    /// It returns the ID of the block, which is freed by `arena_reset` rather than `delete`
+    pub fn arena_insert(&mut self, size: usize) -> Result<usize, MemoryError> {
+        let result = self.arena_allocate(size);
+        // No data is stored, so the requested size counts as data
+        self.usage.update_peaks();
+        result
+    }
+
+    /// Function to allocate a block in the arena for ARENA INSERT, which stores the actual data next
+    pub(crate) fn arena_allocate(&mut self, size: usize) -> Result<usize, MemoryError> {
+        let result = self.arena_place(size);
+        self.record(TraceOp::ArenaInsert { size }, self.trace_block(&result));
+        result
+    }
+
+    /// Function to place a block in the arena without recording it in the trace
+    fn arena_place(&mut self, size: usize) -> Result<usize, MemoryError> {
+        if size == 0 {
+            return Err(MemoryError::ZeroSize);
+        }
+        let block_size = size.next_multiple_of(self.config.get_min_block_size());
+        let fits = |chunk: &ArenaChunk| chunk.size - chunk.used >= block_size;
+        if !self.arena.chunks.last().is_some_and(fits) {
+            let chunk_size = ARENA_CHUNK_SIZE.min(self.config.get_heap_size() / 8).max(block_size);
+            let chunk = self
+                .reserve(chunk_size)
+                .ok_or_else(|| MemoryError::OutOfMemory { requested: size, largest_free: self.largest_free_block() })?;
+            self.arena.chunks.push(ArenaChunk { start: chunk.start, size: chunk.size, used: 0 });
+        }
+
+        let chunk = self.arena.chunks.last_mut().unwrap();
+        let start = chunk.start + chunk.used;
+        chunk.used += block_size;
+        let id = self.next_id;
+        self.next_id += 1;
+        let block = AllocatedBlock::new(start, block_size, id, size);
+        self.usage.add(&block);
+        self.allocated_blocks.insert(id, block);
+        self.memory[start..start + block_size].fill(0);
+        self.mark_allocated(start, block_size, size);
+        self.arena.blocks.push(id);
+        Ok(id)
+    }
+
+    /// Returns the current mark of the arena, to be given to `arena_reset` later
+    pub fn arena_mark(&self) -> usize {
+        self.arena.blocks.len()
+    }
+
+    /// Function to free every arena block allocated after the given mark
+    /// Chunks left empty are given back to the allocation policy. It returns the number of blocks freed
+    pub fn arena_reset(&mut self, mark: usize) -> Result<usize, MemoryError> {
+        let result = self.reset_arena(mark);
+        self.record(TraceOp::ArenaReset { mark }, TraceResult::of(&result));
+        result
+    }
+
+    /// Function to free arena blocks down to a mark without recording it in the trace
+    fn reset_arena(&mut self, mark: usize) -> Result<usize, MemoryError> {
+        let top = self.arena.blocks.len();
+        if mark > top {
+            return Err(MemoryError::InvalidMark { mark, top });
+        }
+        let freed: Vec<usize> = self.arena.blocks.drain(mark..).collect();
+        for id in freed {
+            if let Some(block) = self.allocated_blocks.remove(&id) {
+                self.usage.remove(&block);
+                self.mark_freed(id, block.start, block.size);
+                self.retire(Handle::new(id, block.generation), Staleness::Freed);
+            }
+        }
+        self.arena.update_usage(&self.allocated_blocks);
+        while let Some(chunk) = self.arena.chunks.last().copied().filter(|chunk| chunk.used == 0) {
+            self.arena.chunks.pop();
+            self.release_range(FreeBlock::new(chunk.start, chunk.size));
+        }
+        Ok(top - mark)
+    }
+
+    /// Returns the arena of the manager
+    pub fn arena(&self) -> &Arena {
+        &self.arena
+    }
+
+    /// Returns true if the block with the given ID was allocated in the arena
+    pub fn is_arena_block(&self, id: usize) -> bool {
+        self.arena.blocks.contains(&id)
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeSet;
+
+use super::config::MemoryConfig;
+use super::error::MemoryError;
+use super::free_block::FreeBlock;
+use super::policy::{AllocationPolicy, PolicyCounters, verify_buddy_blocks};
+
+/// BuddyPolicy is the default allocation policy. Requests are rounded up to the next power of two,
+/// the smallest free block that is large enough is split in halves until it matches, and freed
+/// blocks are merged back with their buddy.
+///
+/// Free blocks are kept in one list per order, where a block of order `k` is `min_block_size << k`
+/// bytes long. Each list is ordered by start address, so allocation always picks the lowest free
+/// address of the smallest usable order, and the buddy of a freed block is found with a direct lookup.
+#[derive(Clone, Debug)]
+pub struct BuddyPolicy {
+    heap_size: usize,
+    min_block_size: usize,
+    free_lists: Vec<BTreeSet<usize>>,
+    counters: PolicyCounters,
+}
+
+/// Implement BuddyPolicy struct
+impl BuddyPolicy {
+    /// Creates a new buddy policy managing a single free block of `heap_size` bytes.
+    /// Fails if the heap size is not a power of two.
+    pub fn new(heap_size: usize) -> Result<Self, MemoryError> {
+        let config = MemoryConfig::new().with_heap_size(heap_size);
+        config.validate()?;
+        Ok(BuddyPolicy::with_config(&config))
+    }
+
+    /// Creates a new buddy policy for a validated configuration.
+    /// The heap starts out as one free block of the largest order per `max_block_size` bytes.
+    pub fn with_config(config: &MemoryConfig) -> Self {
+        let max_order = config.get_max_order();
+        let max_block_size = config.get_max_block_size();
+        let mut free_lists = vec![BTreeSet::new(); max_order + 1];
+        free_lists[max_order].extend((0..config.get_heap_size()).step_by(max_block_size));
+        BuddyPolicy {
+            heap_size: config.get_heap_size(),
+            min_block_size: config.get_min_block_size(),
+            free_lists,
+            counters: PolicyCounters::default(),
+        }
+    }
+
+    /// Returns the highest order managed by the policy.
+    fn max_order(&self) -> usize {
+        self.free_lists.len() - 1
+    }
+
+    /// Returns the size in bytes of a block of the given order.
+    fn order_size(&self, order: usize) -> usize {
+        self.min_block_size << order
+    }
+
+    /// Returns the order of a block of the given size.
+    fn order_of(&self, size: usize) -> usize {
+        (size.trailing_zeros() - self.min_block_size.trailing_zeros()) as usize
+    }
+}
+
+/// Implement AllocationPolicy for BuddyPolicy
+impl AllocationPolicy for BuddyPolicy {
+    fn name(&self) -> &'static str {
+        "buddy"
+    }
+
+    fn block_size(&self, requested: usize) -> usize {
+        requested.next_power_of_two().max(self.min_block_size)
+    }
+
+    /// Takes the lowest block of the smallest order that fits and splits it until the size matches
+    fn allocate(&mut self, requested: usize) -> Option<FreeBlock> {
+        if requested > self.order_size(self.max_order()) {
+            self.counters.failed_allocations += 1;
+            return None;
+        }
+        let order = self.order_of(self.block_size(requested));
+
+        // Each order looked at counts as one search step
+        let found = (order..=self.max_order()).find(|&o| !self.free_lists[o].is_empty());
+        self.counters.search_steps += found.unwrap_or(self.max_order()) - order + 1;
+        let Some(mut current) = found else {
+            self.counters.failed_allocations += 1;
+            return None;
+        };
+        let start = self.free_lists[current].pop_first()?;
+
+        // Split until size matches, keeping the left half and freeing the right one
+        while current > order {
+            current -= 1;
+            let right_start = start + self.order_size(current);
+            self.free_lists[current].insert(right_start);
+            self.counters.splits += 1;
+        }
+        self.counters.allocations += 1;
+
+        let mut block = FreeBlock::new(start, self.order_size(order));
+        block.is_free = false;
+        Some(block)
+    }
+
+    /// Frees the block and merges it with its buddy for as long as the buddy is free
+    fn release(&mut self, block: FreeBlock) {
+        let mut start = block.start;
+        let mut order = self.order_of(block.size);
+        self.counters.releases += 1;
+
+        while order < self.max_order() {
+            let buddy = start ^ self.order_size(order);
+            if !self.free_lists[order].remove(&buddy) {
+                break;
+            }
+            start = usize::min(start, buddy);
+            order += 1;
+            self.counters.merges += 1;
+        }
+
+        self.free_lists[order].insert(start);
+    }
+
+    /// Blocks are merged as soon as they are released, so there is never anything left to merge
+    fn merge_free_blocks(&mut self) {}
+
+    fn free_blocks(&self) -> Vec<FreeBlock> {
+        self.free_lists
+            .iter()
+            .enumerate()
+            .flat_map(|(order, starts)| starts.iter().map(move |&start| FreeBlock::new(start, self.order_size(order))))
+            .collect()
+    }
+
+    /// Puts each block in the list of its order, checking that it is a properly aligned buddy block
+    fn restore_free_blocks(&mut self, free_blocks: &[FreeBlock]) -> Result<(), String> {
+        let mut free_lists = vec![BTreeSet::new(); self.free_lists.len()];
+        for block in free_blocks {
+            let size = block.size;
+            if !size.is_power_of_two() || size < self.min_block_size || size > self.order_size(self.max_order()) {
+                return Err(format!("{} is not a valid buddy block size", block));
+            }
+            if block.start % size != 0 {
+                return Err(format!("{} is not aligned to its size", block));
+            }
+            if block.start.checked_add(size).is_none_or(|end| end > self.heap_size) {
+                return Err(format!("{} does not fit in the heap", block));
+            }
+            free_lists[self.order_of(size)].insert(block.start);
+        }
+        self.free_lists = free_lists;
+        Ok(())
+    }
+
+    fn counters(&self) -> PolicyCounters {
+        self.counters
+    }
+
+    fn verify(&self, allocated: &[FreeBlock]) -> Vec<String> {
+        verify_buddy_blocks(&self.free_blocks(), allocated, self.min_block_size, self.order_size(self.max_order()))
+    }
+}
@@ -0,0 +1,208 @@
+use super::config::MemoryConfig;
+use super::free_block::FreeBlock;
+use super::policy::{AllocationPolicy, PolicyCounters, verify_buddy_blocks};
+
+/// Number of blocks tracked by one word of a bitmap
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// BitmapBuddyPolicy is a buddy allocator that keeps its free blocks in a bit tree over the heap
+/// instead of in ordered sets, the way many kernels do.
+///
+/// Each order has a bitmap with one bit per block of that order, set when the block is free as a
+/// whole. Together the bitmaps form a binary tree, where the children of block `i` of order `k` are
+/// blocks `2i` and `2i + 1` of order `k - 1`. The buddy of a block is the bit next to it, and the
+/// lowest free block of an order is the first set bit of its bitmap, so blocks are placed exactly
+/// where BuddyPolicy would place them. The bitmaps never grow after construction.
+#[derive(Clone, Debug)]
+pub struct BitmapBuddyPolicy {
+    min_block_size: usize,
+    heap_size: usize,
+    bitmaps: Vec<Vec<u64>>,
+    free_counts: Vec<usize>, // Number of bits set in each bitmap, to skip empty orders
+    counters: PolicyCounters,
+}
+
+/// Implement BitmapBuddyPolicy struct
+impl BitmapBuddyPolicy {
+    /// Creates a new bitmap buddy policy for a validated configuration.
+    /// The heap starts out as one free block of the largest order per `max_block_size` bytes.
+    pub fn with_config(config: &MemoryConfig) -> Self {
+        let max_order = config.get_max_order();
+        let min_block_size = config.get_min_block_size();
+        let bitmaps = (0..=max_order)
+            .map(|order| vec![0; (config.get_heap_size() / (min_block_size << order)).div_ceil(WORD_BITS)])
+            .collect();
+        let mut policy = BitmapBuddyPolicy {
+            min_block_size,
+            heap_size: config.get_heap_size(),
+            bitmaps,
+            free_counts: vec![0; max_order + 1],
+            counters: PolicyCounters::default(),
+        };
+        for index in 0..config.get_heap_size() / config.get_max_block_size() {
+            policy.set(max_order, index);
+        }
+        policy
+    }
+
+    /// Returns the highest order managed by the policy.
+    fn max_order(&self) -> usize {
+        self.bitmaps.len() - 1
+    }
+
+    /// Returns the size in bytes of a block of the given order.
+    fn order_size(&self, order: usize) -> usize {
+        self.min_block_size << order
+    }
+
+    /// Returns the order of a block of the given size.
+    fn order_of(&self, size: usize) -> usize {
+        (size.trailing_zeros() - self.min_block_size.trailing_zeros()) as usize
+    }
+
+    /// Marks block `index` of the given order as free.
+    fn set(&mut self, order: usize, index: usize) {
+        self.bitmaps[order][index / WORD_BITS] |= 1 << (index % WORD_BITS);
+        self.free_counts[order] += 1;
+    }
+
+    /// Marks block `index` of the given order as not free, returning whether it was free.
+    fn clear(&mut self, order: usize, index: usize) -> bool {
+        let word = &mut self.bitmaps[order][index / WORD_BITS];
+        let bit = 1 << (index % WORD_BITS);
+        let was_free = *word & bit != 0;
+        *word &= !bit;
+        if was_free {
+            self.free_counts[order] -= 1;
+        }
+        was_free
+    }
+
+    /// Returns the index of the lowest free block of the given order, counting the words scanned.
+    fn first_free(&mut self, order: usize) -> Option<usize> {
+        for (i, &word) in self.bitmaps[order].iter().enumerate() {
+            self.counters.search_steps += 1;
+            if word != 0 {
+                return Some(i * WORD_BITS + word.trailing_zeros() as usize);
+            }
+        }
+        None
+    }
+}
+
+/// Implement AllocationPolicy for BitmapBuddyPolicy
+impl AllocationPolicy for BitmapBuddyPolicy {
+    fn name(&self) -> &'static str {
+        "buddy-bitmap"
+    }
+
+    fn block_size(&self, requested: usize) -> usize {
+        requested.next_power_of_two().max(self.min_block_size)
+    }
+
+    /// Takes the lowest block of the smallest order that has one and splits it until the size matches
+    fn allocate(&mut self, requested: usize) -> Option<FreeBlock> {
+        if requested > self.order_size(self.max_order()) {
+            self.counters.failed_allocations += 1;
+            return None;
+        }
+        let order = self.order_of(self.block_size(requested));
+
+        let Some(mut current) = (order..=self.max_order()).find(|&o| self.free_counts[o] > 0) else {
+            self.counters.failed_allocations += 1;
+            return None;
+        };
+        let mut index = self.first_free(current)?;
+        self.clear(current, index);
+
+        // Split until size matches, keeping the left child and freeing the right one
+        while current > order {
+            current -= 1;
+            index *= 2;
+            self.set(current, index + 1);
+            self.counters.splits += 1;
+        }
+        self.counters.allocations += 1;
+
+        let mut block = FreeBlock::new(index * self.order_size(order), self.order_size(order));
+        block.is_free = false;
+        Some(block)
+    }
+
+    /// Frees the block and merges it with its buddy for as long as the buddy is free
+    fn release(&mut self, block: FreeBlock) {
+        let mut order = self.order_of(block.size);
+        let mut index = block.start / block.size;
+        self.counters.releases += 1;
+
+        while order < self.max_order() && self.clear(order, index ^ 1) {
+            index /= 2;
+            order += 1;
+            self.counters.merges += 1;
+        }
+        self.set(order, index);
+    }
+
+    /// Blocks are merged as soon as they are released, so there is never anything left to merge
+    fn merge_free_blocks(&mut self) {}
+
+    /// Lists the free blocks by order, then by address, like BuddyPolicy
+    fn free_blocks(&self) -> Vec<FreeBlock> {
+        let mut free_blocks = Vec::new();
+        for (order, bitmap) in self.bitmaps.iter().enumerate() {
+            for (i, &word) in bitmap.iter().enumerate() {
+                let mut bits = word;
+                while bits != 0 {
+                    let index = i * WORD_BITS + bits.trailing_zeros() as usize;
+                    free_blocks.push(FreeBlock::new(index * self.order_size(order), self.order_size(order)));
+                    bits &= bits - 1;
+                }
+            }
+        }
+        free_blocks
+    }
+
+    /// Sets the bit of each block, checking that it is a properly aligned buddy block
+    fn restore_free_blocks(&mut self, free_blocks: &[FreeBlock]) -> Result<(), String> {
+        let mut restored = BitmapBuddyPolicy {
+            bitmaps: self.bitmaps.iter().map(|bitmap| vec![0; bitmap.len()]).collect(),
+            free_counts: vec![0; self.free_counts.len()],
+            ..self.clone()
+        };
+        for block in free_blocks {
+            let size = block.size;
+            if !size.is_power_of_two() || size < self.min_block_size || size > self.order_size(self.max_order()) {
+                return Err(format!("{} is not a valid buddy block size", block));
+            }
+            if block.start % size != 0 {
+                return Err(format!("{} is not aligned to its size", block));
+            }
+            if block.start.checked_add(size).is_none_or(|end| end > self.heap_size) {
+                return Err(format!("{} does not fit in the heap", block));
+            }
+            restored.set(self.order_of(size), block.start / size);
+        }
+        *self = restored;
+        Ok(())
+    }
+
+    fn counters(&self) -> PolicyCounters {
+        self.counters
+    }
+
+    /// Also checks that the count of free blocks of each order matches its bitmap
+    fn verify(&self, allocated: &[FreeBlock]) -> Vec<String> {
+        let mut violations =
+            verify_buddy_blocks(&self.free_blocks(), allocated, self.min_block_size, self.order_size(self.max_order()));
+        for (order, bitmap) in self.bitmaps.iter().enumerate() {
+            let set_bits: usize = bitmap.iter().map(|word| word.count_ones() as usize).sum();
+            if set_bits != self.free_counts[order] {
+                violations.push(format!(
+                    "order {} counts {} free block(s) but its bitmap has {}",
+                    order, self.free_counts[order], set_bits
+                ));
+            }
+        }
+        violations
+    }
+}
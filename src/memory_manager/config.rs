@@ -0,0 +1,111 @@
+use std::fmt;
+
+use super::error::MemoryError;
+
+/// MemoryConfig describes the shape of the heap managed by a memory manager.
+/// The heap is `heap_size` bytes long, no block is smaller than `min_block_size` bytes and
+/// no block is larger than `min_block_size << max_order` bytes. When the largest block is smaller
+/// than the heap, the heap starts out as several largest blocks laid out back to back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryConfig {
+    heap_size: usize,
+    min_block_size: usize,
+    max_order: Option<usize>,
+}
+
+/// Implement MemoryConfig struct
+impl MemoryConfig {
+    /// Creates the default configuration: a 64 KiB heap with 1-byte granules in a single block.
+    pub fn new() -> Self {
+        MemoryConfig {
+            heap_size: 65536,
+            min_block_size: 1,
+            max_order: None,
+        }
+    }
+
+    /// Sets the total heap size in bytes.
+    pub fn with_heap_size(mut self, heap_size: usize) -> Self {
+        self.heap_size = heap_size;
+        self
+    }
+
+    /// Sets the minimum block size in bytes. Every allocation is rounded up to at least this size.
+    pub fn with_min_block_size(mut self, min_block_size: usize) -> Self {
+        self.min_block_size = min_block_size;
+        self
+    }
+
+    /// Sets the maximum order, so that the largest block is `min_block_size << max_order` bytes.
+    pub fn with_max_order(mut self, max_order: usize) -> Self {
+        self.max_order = Some(max_order);
+        self
+    }
+
+    /// Returns the heap_size field of the configuration.
+    pub fn get_heap_size(&self) -> usize {
+        self.heap_size
+    }
+
+    /// Returns the min_block_size field of the configuration.
+    pub fn get_min_block_size(&self) -> usize {
+        self.min_block_size
+    }
+
+    /// Returns the maximum order. If none was set, the largest block spans the whole heap.
+    pub fn get_max_order(&self) -> usize {
+        self.max_order.unwrap_or_else(|| {
+            (self.heap_size.max(1).ilog2() as usize).saturating_sub(self.min_block_size.max(1).ilog2() as usize)
+        })
+    }
+
+    /// Returns the size of the largest block in bytes.
+    pub fn get_max_block_size(&self) -> usize {
+        self.min_block_size << self.get_max_order()
+    }
+
+    /// Checks that the sizes are powers of two and that the largest block fits in the heap
+    pub fn validate(&self) -> Result<(), MemoryError> {
+        if !self.heap_size.is_power_of_two() {
+            return Err(MemoryError::InvalidConfig(format!("Heap size must be a power of two, got {}", self.heap_size)));
+        }
+        if !self.min_block_size.is_power_of_two() {
+            return Err(MemoryError::InvalidConfig(format!("Minimum block size must be a power of two, got {}", self.min_block_size)));
+        }
+        if self.min_block_size > self.heap_size {
+            return Err(MemoryError::InvalidConfig(format!(
+                "Minimum block size {} is larger than the heap size {}",
+                self.min_block_size, self.heap_size
+            )));
+        }
+        let max_order = self.get_max_order();
+        let max_block_order = max_order.saturating_add(self.min_block_size.trailing_zeros() as usize);
+        if max_block_order > self.heap_size.trailing_zeros() as usize {
+            return Err(MemoryError::InvalidConfig(format!(
+                "Maximum order {} gives blocks larger than the heap size {}",
+                max_order, self.heap_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Implement Default for MemoryConfig
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        MemoryConfig::new()
+    }
+}
+
+/// Implement Display for MemoryConfig
+impl fmt::Display for MemoryConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "MemoryConfig: heap_size={}, min_block_size={}, max_order={}",
+            self.heap_size,
+            self.min_block_size,
+            self.get_max_order()
+        )
+    }
+}
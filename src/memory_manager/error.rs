@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use super::handle::{Handle, Staleness};
+use super::shadow::BadAccess;
+
+/// MemoryError describes why an operation of the memory manager failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    /// No free block is large enough for the request.
+    OutOfMemory { requested: usize, largest_free: usize },
+    /// No allocated block has the given ID.
+    UnknownId(usize),
+    /// The data does not fit in the block it should be written to.
+    DataTooLarge { data_size: usize, block_size: usize },
+    /// A block of zero bytes was requested.
+    ZeroSize,
+    /// A command could not be understood.
+    InvalidCommand(String),
+    /// The heap configuration is not usable.
+    InvalidConfig(String),
+    /// No allocation policy has the given name.
+    UnknownPolicy(String),
+    /// A snapshot is damaged, from an unsupported version, or inconsistent.
+    InvalidSnapshot(String),
+    /// No slab cache has the given name.
+    UnknownCache(String),
+    /// A slab cache with the given name already exists.
+    CacheExists(String),
+    /// The block is a page owned by a slab cache and cannot be changed directly.
+    SlabPage { id: usize, cache: String },
+    /// The block belongs to the arena, which only frees blocks with ARENA RESET.
+    ArenaBlock(usize),
+    /// An arena mark is past the blocks currently in the arena.
+    InvalidMark { mark: usize, top: usize },
+    /// Reading or writing a file failed.
+    Io(String),
+    /// The block named by a handle or ID was freed or moved.
+    StaleHandle { handle: Handle, staleness: Staleness },
+    /// A raw access reaches past the end of the heap.
+    AddressOutOfRange { address: usize, len: usize, heap_size: usize },
+    /// A strict raw access reaches bytes outside the allocated block holding its first byte, if any.
+    OutsideBlock { address: usize, len: usize, block: Option<usize> },
+    /// A raw access was refused by the shadow memory.
+    BadAccess(BadAccess),
+    /// Invariants of the heap are broken, as found by `verify`.
+    HeapCorrupted(Vec<String>),
+}
+
+/// Implement Display for MemoryError
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryError::OutOfMemory { requested, largest_free } => write!(
+                f,
+                "No suitable block available: requested {} bytes, largest free block is {} bytes",
+                requested, largest_free
+            ),
+            MemoryError::UnknownId(id) => write!(f, "Block with ID {} does not exist", id),
+            MemoryError::DataTooLarge { data_size, block_size } => write!(
+                f,
+                "Data size exceeds block size. Data size: {}, Block size: {}",
+                data_size, block_size
+            ),
+            MemoryError::ZeroSize => write!(f, "Cannot insert zero-sized block"),
+            MemoryError::InvalidCommand(command) => write!(f, "Unknown or invalid command: {}", command),
+            MemoryError::InvalidConfig(reason) => write!(f, "Invalid configuration: {}", reason),
+            MemoryError::UnknownPolicy(name) => write!(f, "Unknown allocation policy '{}'", name),
+            MemoryError::InvalidSnapshot(reason) => write!(f, "Invalid snapshot: {}", reason),
+            MemoryError::UnknownCache(name) => write!(f, "Slab cache '{}' does not exist", name),
+            MemoryError::CacheExists(name) => write!(f, "Slab cache '{}' already exists", name),
+            MemoryError::SlabPage { id, cache } => write!(f, "Block with ID {} is a page of slab cache '{}'", id, cache),
+            MemoryError::ArenaBlock(id) => {
+                write!(f, "Block with ID {} belongs to the arena and can only be freed with ARENA RESET", id)
+            }
+            MemoryError::InvalidMark { mark, top } => {
+                write!(f, "Arena mark {} is past the top of the arena ({} blocks)", mark, top)
+            }
+            MemoryError::Io(reason) => write!(f, "I/O error: {}", reason),
+            MemoryError::StaleHandle { handle, staleness: Staleness::Freed } => write!(f, "Block {} was freed", handle),
+            MemoryError::StaleHandle { handle, staleness: Staleness::Moved { to } } => {
+                write!(f, "Block {} was moved and is now {}", handle, to)
+            }
+            MemoryError::AddressOutOfRange { address, len, heap_size } => {
+                write!(f, "Access of {} bytes at 0x{:04X} is outside the heap of {} bytes", len, address, heap_size)
+            }
+            MemoryError::OutsideBlock { address, len, block: None } => {
+                write!(f, "Access of {} bytes at 0x{:04X} does not start in an allocated block", len, address)
+            }
+            MemoryError::OutsideBlock { address, len, block: Some(id) } => {
+                write!(f, "Access of {} bytes at 0x{:04X} runs past the end of block {}", len, address, id)
+            }
+            MemoryError::BadAccess(access) => write!(f, "{}", access),
+            MemoryError::HeapCorrupted(violations) => {
+                write!(f, "Heap check found {} violation(s): {}", violations.len(), violations.join("; "))
+            }
+        }
+    }
+}
+
+/// Implement Error for MemoryError
+impl Error for MemoryError {}
+
+/// Implement From<io::Error> for MemoryError
+impl From<io::Error> for MemoryError {
+    fn from(error: io::Error) -> Self {
+        MemoryError::Io(error.to_string())
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use super::config::MemoryConfig;
+use super::free_block::FreeBlock;
+use super::policy::{AllocationPolicy, PolicyCounters, verify_coalesced_blocks};
+
+/// FitStrategy decides which free range a FitPolicy carves a new block from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FitStrategy {
+    /// The smallest free range that is large enough.
+    BestFit,
+    /// The largest free range.
+    WorstFit,
+    /// The first free range that is large enough, searching on from where the last block was placed.
+    NextFit,
+}
+
+/// FitPolicy is a variable-size allocation policy. Requests are only rounded up to a multiple of
+/// the minimum block size, a block is carved from the front of a free range chosen by the strategy,
+/// and freed blocks are merged with the free ranges directly before and after them.
+///
+/// Free ranges are kept in a map from start address to size, so neighbours are found with a
+/// range lookup. The maximum order of the configuration only applies to the buddy policy.
+#[derive(Clone, Debug)]
+pub struct FitPolicy {
+    strategy: FitStrategy,
+    min_block_size: usize,
+    heap_size: usize,
+    free: BTreeMap<usize, usize>,
+    cursor: usize, // Address the next-fit search starts from
+    counters: PolicyCounters,
+}
+
+/// Implement FitPolicy struct
+impl FitPolicy {
+    /// Creates a new fit policy managing a single free range covering the heap of a validated configuration.
+    pub fn with_config(strategy: FitStrategy, config: &MemoryConfig) -> Self {
+        FitPolicy {
+            strategy,
+            min_block_size: config.get_min_block_size(),
+            heap_size: config.get_heap_size(),
+            free: BTreeMap::from([(0, config.get_heap_size())]),
+            cursor: 0,
+            counters: PolicyCounters::default(),
+        }
+    }
+
+    /// Returns the strategy used to pick free ranges.
+    pub fn strategy(&self) -> FitStrategy {
+        self.strategy
+    }
+
+    /// Returns the start of the free range the strategy picks for a block of `size` bytes,
+    /// with the number of free ranges looked at to find it.
+    fn find(&self, size: usize) -> (Option<usize>, usize) {
+        let fits = |&(_, &free_size): &(&usize, &usize)| free_size >= size;
+        match self.strategy {
+            // Best-fit and worst-fit look at every range. Ties go to the lowest address, since the map is ordered by start
+            FitStrategy::BestFit => {
+                let best = self.free.iter().filter(fits).min_by_key(|&(_, &free_size)| free_size);
+                (best.map(|(&start, _)| start), self.free.len())
+            }
+            FitStrategy::WorstFit => {
+                let worst = self.free.iter().filter(fits).max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)));
+                (worst.map(|(&start, _)| start), self.free.len())
+            }
+            FitStrategy::NextFit => {
+                // Ranges from the cursor on are searched first, then the search wraps around to address 0
+                let wrapped = self.free.range(self.cursor..).chain(self.free.range(..self.cursor));
+                match wrapped.enumerate().find(|(_, range)| fits(range)) {
+                    Some((steps, (&start, _))) => (Some(start), steps + 1),
+                    None => (None, self.free.len()),
+                }
+            }
+        }
+    }
+
+    /// Adds a free range, merging it with the free ranges that touch it.
+    fn insert_free(&mut self, mut start: usize, mut size: usize) {
+        if let Some((&before, &before_size)) = self.free.range(..start).next_back()
+            && before + before_size == start
+        {
+            self.free.remove(&before);
+            start = before;
+            size += before_size;
+            self.counters.merges += 1;
+        }
+        if let Some(after_size) = self.free.remove(&(start + size)) {
+            size += after_size;
+            self.counters.merges += 1;
+        }
+        self.free.insert(start, size);
+    }
+}
+
+/// Implement AllocationPolicy for FitPolicy
+impl AllocationPolicy for FitPolicy {
+    fn name(&self) -> &'static str {
+        match self.strategy {
+            FitStrategy::BestFit => "best-fit",
+            FitStrategy::WorstFit => "worst-fit",
+            FitStrategy::NextFit => "next-fit",
+        }
+    }
+
+    fn block_size(&self, requested: usize) -> usize {
+        requested.max(1).next_multiple_of(self.min_block_size)
+    }
+
+    /// Carves the block from the front of the chosen range, leaving the rest of the range free
+    fn allocate(&mut self, requested: usize) -> Option<FreeBlock> {
+        let size = self.block_size(requested);
+        let (found, steps) = if requested > self.heap_size { (None, 0) } else { self.find(size) };
+        self.counters.search_steps += steps;
+        let Some(start) = found else {
+            self.counters.failed_allocations += 1;
+            return None;
+        };
+        let free_size = self.free.remove(&start)?;
+        if free_size > size {
+            self.free.insert(start + size, free_size - size);
+            self.counters.splits += 1;
+        }
+        self.cursor = (start + size) % self.heap_size;
+        self.counters.allocations += 1;
+
+        let mut block = FreeBlock::new(start, size);
+        block.is_free = false;
+        Some(block)
+    }
+
+    fn release(&mut self, block: FreeBlock) {
+        self.counters.releases += 1;
+        self.insert_free(block.start, block.size);
+    }
+
+    /// Free ranges are merged as soon as they are released, so there is never anything left to merge
+    fn merge_free_blocks(&mut self) {}
+
+    fn free_blocks(&self) -> Vec<FreeBlock> {
+        self.free.iter().map(|(&start, &size)| FreeBlock::new(start, size)).collect()
+    }
+
+    /// Rebuilds the free ranges, merging any that touch. The next-fit search starts over from address 0
+    fn restore_free_blocks(&mut self, free_blocks: &[FreeBlock]) -> Result<(), String> {
+        let mut restored = FitPolicy { free: BTreeMap::new(), cursor: 0, ..self.clone() };
+        for block in free_blocks {
+            if block.size == 0 || block.start.checked_add(block.size).is_none_or(|end| end > self.heap_size) {
+                return Err(format!("{} does not fit in the heap", block));
+            }
+            restored.insert_free(block.start, block.size);
+        }
+        restored.counters = self.counters;
+        *self = restored;
+        Ok(())
+    }
+
+    fn counters(&self) -> PolicyCounters {
+        self.counters
+    }
+
+    fn verify(&self, allocated: &[FreeBlock]) -> Vec<String> {
+        let mut violations = verify_coalesced_blocks(&self.free_blocks(), allocated, self.min_block_size);
+        if self.cursor >= self.heap_size {
+            violations.push(format!("next-fit cursor 0x{:04X} is outside the heap", self.cursor));
+        }
+        violations
+    }
+}
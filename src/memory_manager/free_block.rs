@@ -2,7 +2,7 @@ use std::fmt;
 
 /// FreeBlock struct representing a block of memory that does not contain data.
 #[derive(Clone, Copy, Debug)]
-pub(crate) struct FreeBlock {
+pub struct FreeBlock {
     pub start: usize,
     pub size: usize,
     pub is_free: bool,
@@ -12,7 +12,7 @@ pub(crate) struct FreeBlock {
 /// This struct represents a block of memory that is free and does not contain any data.
 impl FreeBlock {
     /// Creates a new FreeBlock with the specified start position and size.
-    pub(crate) fn new(start: usize, size: usize) -> Self {
+    pub fn new(start: usize, size: usize) -> Self {
         FreeBlock { start, size, is_free: true }
     }
 
@@ -20,7 +20,7 @@ impl FreeBlock {
         self.start
     }
     /// Returns the size field of the free block.
-    pub(crate) fn get_size(&self) -> usize {
+    pub fn get_size(&self) -> usize {
         self.size
     }
 }
@@ -0,0 +1,147 @@
+use std::fmt;
+
+use super::MemoryManager;
+use super::allocated_block::AllocatedBlock;
+use super::error::MemoryError;
+use super::free_block::FreeBlock;
+use super::trace::TraceOp;
+
+/// Number of retired IDs the manager remembers; older ones are reported as unknown IDs again
+pub const RETIRED_HISTORY: usize = 4096;
+
+/// Handle names an allocated block by its ID and generation, written `id#generation`.
+/// IDs are never given out twice, and the generation of a block goes up each time `update_stable`
+/// moves it, so a handle kept from before the block was freed or moved is recognised as stale.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    id: usize,
+    generation: u32,
+}
+
+/// Staleness tells why a handle or ID no longer names a live block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Staleness {
+    /// The block was freed.
+    Freed,
+    /// The block was moved, and now lives under the given handle.
+    Moved { to: Handle },
+}
+
+/// Implement Handle struct
+impl Handle {
+    pub(crate) fn new(id: usize, generation: u32) -> Self {
+        Handle { id, generation }
+    }
+
+    /// Returns the ID of the block
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns the generation of the block
+    pub fn get_generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// Implement Display for Handle
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}#{}", self.id, self.generation)
+    }
+}
+
+/// Handles of the memory manager
+/// The manager remembers how the last RETIRED_HISTORY retired IDs went away, so that using one again
+/// reports a stale handle instead of an unknown ID. Snapshots keep neither generations nor this history.
+impl MemoryManager {
+    /// Returns the handle of the live block with the given ID
+    pub fn handle(&self, id: usize) -> Result<Handle, MemoryError> {
+        match self.allocated_blocks.get(&id) {
+            Some(block) => Ok(Handle::new(id, block.generation)),
+            None => Err(self.missing(id)),
+        }
+    }
+
+    /// Function to find the ID of the block named by a handle
+    /// It fails with a stale handle error if the block was freed or moved since the handle was taken
+    pub fn resolve(&self, handle: Handle) -> Result<usize, MemoryError> {
+        match self.allocated_blocks.get(&handle.id) {
+            Some(block) if block.generation == handle.generation => Ok(handle.id),
+            Some(block) if block.generation > handle.generation => Err(MemoryError::StaleHandle {
+                handle,
+                staleness: Staleness::Moved { to: Handle::new(handle.id, block.generation) },
+            }),
+            Some(_) => Err(MemoryError::UnknownId(handle.id)),
+            None => match self.retired.get(&handle.id) {
+                Some(&(last, staleness)) if handle.generation <= last.generation => {
+                    Err(MemoryError::StaleHandle { handle, staleness })
+                }
+                _ => Err(MemoryError::UnknownId(handle.id)),
+            },
+        }
+    }
+
+    /// Function to describe why there is no live block with the given ID
+    pub(crate) fn missing(&self, id: usize) -> MemoryError {
+        match self.retired.get(&id) {
+            Some(&(handle, staleness)) => MemoryError::StaleHandle { handle, staleness },
+            None => MemoryError::UnknownId(id),
+        }
+    }
+
+    /// Function to remember how the block with the given handle went away
+    /// Once more than RETIRED_HISTORY IDs are remembered, the one retired first is forgotten
+    pub(crate) fn retire(&mut self, handle: Handle, staleness: Staleness) {
+        if self.retired.insert(handle.id, (handle, staleness)).is_none() {
+            self.retired_order.push_back(handle.id);
+        }
+        while self.retired_order.len() > RETIRED_HISTORY {
+            if let Some(oldest) = self.retired_order.pop_front() {
+                self.retired.remove(&oldest);
+            }
+        }
+    }
+
+    /// Function to update data in an allocated block, keeping its ID when it has to be reallocated
+    /// A block that moves gets the next generation, so handles taken before the move become stale
+    /// It returns the handle of the block holding the data
+    pub fn update_stable(&mut self, id: usize, new_data: &[u8]) -> Result<Handle, MemoryError> {
+        let result = self.update_stable_block(id, new_data);
+        let placed = result.clone().map(|handle| handle.id);
+        self.record(TraceOp::UpdateStable { id, len: new_data.len() }, self.trace_block(&placed));
+        result
+    }
+
+    /// Function to update a block in place or move it under the same ID, without recording it in the trace
+    fn update_stable_block(&mut self, id: usize, new_data: &[u8]) -> Result<Handle, MemoryError> {
+        let Some(block) = self.allocated_blocks.get(&id) else {
+            return Err(self.missing(id));
+        };
+        if new_data.len() <= self.capacity(block) || self.is_arena_block(id) {
+            self.update_block(id, new_data)?;
+            return self.handle(id);
+        }
+        self.check_not_slab_page(id)?;
+
+        let requested = new_data.len();
+        let placed = self
+            .reserve(requested + self.redzone())
+            .ok_or_else(|| MemoryError::OutOfMemory { requested, largest_free: self.largest_free_block() })?;
+        let old = self.allocated_blocks.remove(&id).unwrap();
+        self.usage.remove(&old);
+        self.mark_freed(id, old.start, old.size);
+        self.release_range(FreeBlock::new(old.start, old.size));
+
+        let mut block = AllocatedBlock::new(placed.start, placed.size, id, requested);
+        block.generation = old.generation + 1;
+        self.memory[block.start..block.start + block.size].fill(0);
+        self.memory[block.start..block.start + requested].copy_from_slice(new_data);
+        self.mark_allocated(block.start, block.size, requested);
+        self.mark_written(block.start, requested);
+        self.usage.add(&block);
+        self.usage.update_peaks();
+        self.allocated_blocks.insert(id, block);
+        self.handle(id)
+    }
+}
@@ -1,99 +1,226 @@
-use crate::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::command::{Command, parse_line};
+use crate::{DataFormat, format_data};
 
 pub mod allocated_block;
+pub mod arena;
+pub mod buddy;
+pub mod buddy_bitmap;
+pub mod config;
+pub mod error;
+pub mod fit;
 pub mod free_block;
+pub mod handle;
 pub mod memory_block;
+pub mod outcome;
+pub mod policy;
+pub mod raw;
+pub mod serialize;
+pub mod shadow;
+pub mod sink;
+pub mod slab;
+pub mod snapshot;
+pub mod stats;
+pub mod tlsf;
+pub mod trace;
+pub mod verify;
+pub mod visual;
 
-use memory_block::MemoryBlock;
-use allocated_block::AllocatedBlock;
+use allocated_block::{AllocatedBlock, DataMemoryBlock};
+use arena::Arena;
+use buddy::BuddyPolicy;
+use config::MemoryConfig;
+use error::MemoryError;
+use outcome::CommandOutcome;
+use sink::OutputSink;
+use slab::SlabCache;
+use stats::Usage;
 use free_block::FreeBlock;
-use allocated_block::DataMemoryBlock;
+use handle::{Handle, Staleness};
+use shadow::Shadow;
+use policy::{AllocationPolicy, PolicyCounters, create_policy};
+use trace::{Trace, TraceOp, TraceResult};
 
 
 
 /// Define the MemoryManager struct
 pub struct MemoryManager {
-    memory: [u8; 65535],
-    free_blocks: Vec<FreeBlock>, // Using Vec to manage free blocks
+    config: MemoryConfig,
+    memory: Vec<u8>,
+    policy: Box<dyn AllocationPolicy>, // Decides where blocks are placed and tracks free blocks
     allocated_blocks: HashMap<usize, AllocatedBlock>,
     next_id: usize,
+    caches: BTreeMap<String, SlabCache>, // Slab caches by name, whose pages are allocated blocks
+    arena: Arena, // Bump allocator for short-lived blocks, whose chunks are taken from the policy
+    usage: Usage, // Running totals and peaks of the allocated blocks
+    default_format: DataFormat, // Format used by READ and DUMP when none is given
+    sink: Option<Box<dyn OutputSink>>, // Receives command outcomes, silent when None
+    trace: Option<Trace>, // Mutations recorded since `start_trace`, not recording when None
+    verify_commands: bool, // Check the heap after every executed command
+    retired: HashMap<usize, (Handle, Staleness)>, // Last handle of each freed or moved ID, and why it went
+    retired_order: VecDeque<usize>, // IDs in `retired` from the first retired, so the oldest can be forgotten
+    shadow: Option<Shadow>, // State of every byte of the heap, not checking raw accesses when None
 }
 
 /// MemoryManager struct to manage memory allocation and deallocation
-/// It contains a memory array, a vector of free blocks, a hashmap of allocated blocks, and an ID counter.
+/// It contains a memory array, an allocation policy, a hashmap of allocated blocks, and an ID counter.
 impl MemoryManager {
     pub fn new() -> MemoryManager {
+        let config = MemoryConfig::new(); // Initial large block of 64 KiB
+        MemoryManager::from_parts(config, Box::new(BuddyPolicy::with_config(&config)))
+    }
+
+    /// Creates a buddy memory manager with the given heap size, minimum block size and maximum order
+    /// It returns an error if the configuration is not made of powers of two
+    pub fn with_config(config: MemoryConfig) -> Result<MemoryManager, MemoryError> {
+        config.validate()?;
+        Ok(MemoryManager::from_parts(config, Box::new(BuddyPolicy::with_config(&config))))
+    }
+
+    /// Creates a memory manager that places blocks using the given allocation policy
+    /// The policy must manage a heap of the size given in the configuration
+    pub fn with_policy(config: MemoryConfig, policy: Box<dyn AllocationPolicy>) -> Result<MemoryManager, MemoryError> {
+        config.validate()?;
+        Ok(MemoryManager::from_parts(config, policy))
+    }
+
+    /// Creates a memory manager using the allocation policy with the given name, such as "best-fit"
+    /// It returns an error if the configuration is invalid or no policy has that name
+    pub fn with_policy_name(config: MemoryConfig, name: &str) -> Result<MemoryManager, MemoryError> {
+        config.validate()?;
+        Ok(MemoryManager::from_parts(config, create_policy(name, &config)?))
+    }
+
+    fn from_parts(config: MemoryConfig, policy: Box<dyn AllocationPolicy>) -> MemoryManager {
         MemoryManager {
-            memory: [0; 65535],
-            free_blocks: vec![FreeBlock::new(0, 65536)], // Initial large block
+            config,
+            memory: vec![0; config.get_heap_size()],
+            policy,
             allocated_blocks: HashMap::new(),
             next_id: 0,
+            caches: BTreeMap::new(),
+            arena: Arena::default(),
+            usage: Usage::default(),
+            default_format: DataFormat::Text,
+            sink: None,
+            trace: None,
+            verify_commands: false,
+            retired: HashMap::new(),
+            retired_order: VecDeque::new(),
+            shadow: None,
         }
     }
 
+    /// Returns the configuration of the heap
+    pub fn config(&self) -> &MemoryConfig {
+        &self.config
+    }
+
+    /// Sets the format used by READ and DUMP commands that do not name one
+    pub fn set_default_format(&mut self, format: DataFormat) {
+        self.default_format = format;
+    }
+
+    /// Installs a sink that receives the outcome of every executed command
+    pub fn set_sink(&mut self, sink: Box<dyn OutputSink>) {
+        self.sink = Some(sink);
+    }
+
+    /// Removes the sink, making the manager silent again, and returns it
+    pub fn take_sink(&mut self) -> Option<Box<dyn OutputSink>> {
+        self.sink.take()
+    }
+
+    /// Returns the name of the allocation policy in use
+    pub fn policy_name(&self) -> &'static str {
+        self.policy.name()
+    }
+
+    /// Returns the number of allocations, splits, merges and search steps done by the policy
+    pub fn policy_counters(&self) -> PolicyCounters {
+        self.policy.counters()
+    }
+
+    /// Function to switch to the allocation policy with the given name
+    /// The policy can only be changed while no blocks are allocated, since it starts from an empty heap
+    pub fn set_policy(&mut self, name: &str) -> Result<(), MemoryError> {
+        let result = self.switch_policy(name);
+        self.record(TraceOp::Policy { name: name.to_string() }, TraceResult::of(&result));
+        result
+    }
+
+    /// Function to switch the allocation policy without recording it in the trace
+    fn switch_policy(&mut self, name: &str) -> Result<(), MemoryError> {
+        if !self.allocated_blocks.is_empty() {
+            return Err(MemoryError::InvalidCommand(format!(
+                "cannot switch to policy '{}' while {} block(s) are allocated",
+                name,
+                self.allocated_blocks.len()
+            )));
+        }
+        self.policy = create_policy(name, &self.config)?;
+        // The quarantined ranges belonged to the old policy, so they are dropped with it
+        self.rebuild_shadow();
+        Ok(())
+    }
+
     /// Function to set data in a memory block
     /// This function will check if the block ID exists and if the data fits in the block size
-    pub fn set(&mut self, id: usize, data: &[u8]) -> Result<(), String> {
+    pub fn set(&mut self, id: usize, data: &[u8]) -> Result<(), MemoryError> {
+        let result = self.store(id, data);
+        self.record(TraceOp::Set { id, len: data.len() }, TraceResult::of(&result));
+        result
+    }
+
+    /// Function to copy data into a memory block without recording it in the trace
+    fn store(&mut self, id: usize, data: &[u8]) -> Result<(), MemoryError> {
+        self.check_not_slab_page(id)?;
+        let capacity = self.allocated_blocks.get(&id).map_or(0, |block| self.capacity(block));
         if let Some(block) = self.allocated_blocks.get_mut(&id) {
-            if data.len() <= block.size {
+            if data.len() <= capacity {
                 // Copy the new data into the memory starting at block.start
                 self.memory[block.start..(block.start + data.len())].copy_from_slice(data);
                 // Update the actual used size of data in the block
+                self.usage.change_data(block.data_size, data.len());
                 block.data_size = data.len();
-                println!("Data successfully updated in block ID: {}", id);
+                let start = block.start;
+                self.mark_written(start, data.len());
                 Ok(())
             } else {
-                Err(format!("Data size exceeds block size. Data size: {}, Block size: {}", data.len(), block.size))
+                Err(MemoryError::DataTooLarge { data_size: data.len(), block_size: capacity })
             }
         } else {
-            Err("Block ID not found".to_string())
+            Err(self.missing(id))
         }
     }
 
-    /// This function uses 'allocate' to get a block and then inserts data into it
-    /// It returns the ID of the allocated block or an error message
+    /// This function uses 'allocate' to get a block for the data
+    /// It returns the ID of the allocated block or an error
     /// It will also check if the data fits in the block size and update the data size accordingly
-    pub fn insert(&mut self, data_size: usize) -> Result<usize, String> {
+    pub fn insert(&mut self, data_size: usize) -> Result<usize, MemoryError> {
+        let result = self.insert_block(data_size);
+        // No data is stored, so the requested size counts as data
+        self.usage.update_peaks();
+        result
+    }
+
+    /// Function to allocate a block for INSERT and record it in the trace
+    /// The peak of data bytes is not updated here, since INSERT stores the actual data next
+    fn insert_block(&mut self, data_size: usize) -> Result<usize, MemoryError> {
         // Check if the data size is zero
-        if data_size == 0 {
-            return Err("Cannot insert zero-sized block".to_string()); // If zero, return an error
-        }
-        let required_size = data_size.next_power_of_two();
-    
-        // Find the first block that is large enough
-        let mut best_index = None;
-        for (i, block) in self.free_blocks.iter().enumerate() {
-            if block.is_free && block.size >= required_size {
-                best_index = Some(i);
-                break;
-            }
-        }
-    
-        if let Some(index) = best_index {
-            let mut block = self.free_blocks.remove(index);
-    
-            // Split until size matches
-            while block.size > required_size {
-                let half_size = block.size / 2;
-                let right_block = FreeBlock::new(block.start + half_size, half_size);
-                self.free_blocks.push(right_block);
-                block.size = half_size; // Keep the left half
-            }
-    
-            block.is_free = false;
-            let id = self.next_id;
-            self.allocated_blocks.insert(id, AllocatedBlock::new(block.start, block.size, id, data_size));
-            self.next_id += 1;
-            Ok(id)
+        let result = if data_size == 0 {
+            Err(MemoryError::ZeroSize) // If zero, return an error
         } else {
-            Err("No suitable block available".to_string())
-        }
+            self.allocate(data_size)
+        };
+        self.record(TraceOp::Insert { size: data_size }, self.trace_block(&result));
+        result
     }
 
     /// Function to read data from a memory block
     /// This function will check if the block ID exists and return the block details
-    pub fn read(&self, id: usize) -> Result<AllocatedBlock, String> {
+    pub fn read(&self, id: usize) -> Result<AllocatedBlock, MemoryError> {
         if let Some(block) = self.allocated_blocks.get(&id) {
             let start_addr = block.get_start();
             let end_addr = block.get_start() + block.get_size() - 1; // Calculate end address
@@ -104,144 +231,120 @@ impl MemoryManager {
 
             Ok(AllocatedBlock::new(start_addr, end_addr, size, data_length))
         } else {
-            // Check if it's a free block, otherwise return error about missing block
-            Err(format!("Nothing at [{}]", id))
+            // Return an error about the missing block
+            Err(self.missing(id))
         }
     }
     
     /// Function to read data from the memory manager and format it for output
     /// This function will check if the block ID exists and return the formatted string
     /// It will also include the start and end addresses, status, size, and data
-    pub fn read_formatted(&self, id: usize) -> Result<String, String> {
+    pub fn read_formatted(&self, id: usize) -> Result<String, MemoryError> {
+        self.read_formatted_as(id, DataFormat::Text)
+    }
+
+    /// Function to read data from a memory block and format it for output, showing the data in the given format
+    /// Hex data is shown without quotes so it can be pasted back into an INSERT or UPDATE
+    /// JSON output describes the whole block as a single object
+    pub fn read_formatted_as(&self, id: usize, format: DataFormat) -> Result<String, MemoryError> {
+        if format == DataFormat::Json {
+            return self.read_json(id);
+        }
         if let Some(block) = self.allocated_blocks.get(&id) {
             let data_slice = &self.memory[block.start..block.start + block.data_size];
-            let data_string = String::from_utf8_lossy(data_slice);
             Ok(format!(
-                "READ data: Start Address: 0x{:04X}, End Address: 0x{:04X}, Status: Allocated, Size: {} bytes, Data: '{}'",
-                block.start, block.start + block.data_size - 1, block.data_size, data_string
+                "READ data: Start Address: 0x{:04X}, End Address: 0x{:04X}, Status: Allocated, Size: {} bytes, Data: {}",
+                block.start, block.get_end(), block.data_size, display_data(data_slice, format)
             ))
         } else {
-            Err(format!("Block with ID {} does not exist.", id))
+            Err(self.missing(id))
         }
     }
     
     /// Function to allocate a block of memory
-    /// This function will find the best fitting free block, split it if necessary, and return the ID of the allocated block
-    pub fn allocate(&mut self, requested_size: usize) -> Result<usize, String> {
-        let required_size = requested_size.next_power_of_two();
-        let mut best_index = None;
-    
-        for (i, block) in self.free_blocks.iter().enumerate() {
-            if block.is_free && block.size >= required_size {
-                best_index = Some(i);
-                break;
-            }
-        }
-    
-        if let Some(index) = best_index {
-            let mut block = self.free_blocks.remove(index);
-    
-            while block.size > required_size {
-                let half_size = block.size / 2;
-                let right_block = FreeBlock::new(block.start + half_size, half_size);
-                self.free_blocks.push(right_block);
-                block.size = half_size;
-            }
-    
-            block.is_free = false;
+    /// This function asks the allocation policy for a block, records it as allocated and returns its ID
+    pub fn allocate(&mut self, requested_size: usize) -> Result<usize, MemoryError> {
+        if let Some(block) = self.reserve(requested_size + self.redzone()) {
             let id = self.next_id;
-            self.allocated_blocks.insert(id, AllocatedBlock::new(block.start, block.size, id, requested_size));
+            let block = AllocatedBlock::new(block.start, block.size, id, requested_size);
+            self.mark_allocated(block.start, block.size, requested_size);
+            self.usage.add(&block);
+            self.allocated_blocks.insert(id, block);
             self.next_id += 1;
             Ok(id)
         } else {
-            Err("No suitable block available".to_string())
+            Err(MemoryError::OutOfMemory { requested: requested_size, largest_free: self.largest_free_block() })
         }
     }
+
+    /// Returns the size of the largest free block, or 0 if the heap is full
+    pub fn largest_free_block(&self) -> usize {
+        self.policy.free_blocks().iter().map(|block| block.size).max().unwrap_or(0)
+    }
     
     
     /// Function to delete a block by ID
-    /// This function will remove the block from the allocated_blocks and add it back to the free_blocks
-    /// It will also merge adjacent free blocks if necessary
-    pub fn delete(&mut self, id: usize) -> Result<(), String> {
+    /// This function will remove the block from the allocated_blocks and hand it back to the allocation policy
+    /// The policy merges adjacent free blocks if necessary
+    pub fn delete(&mut self, id: usize) -> Result<(), MemoryError> {
+        let result = self.free(id);
+        self.record(TraceOp::Delete { id }, TraceResult::of(&result));
+        result
+    }
+
+    /// Function to free a block without recording it in the trace
+    fn free(&mut self, id: usize) -> Result<(), MemoryError> {
+        self.check_not_slab_page(id)?;
+        if self.is_arena_block(id) {
+            return Err(MemoryError::ArenaBlock(id));
+        }
         // Attempt to find and remove the allocated block
         if let Some(block) = self.allocated_blocks.remove(&id) {
-            // Add the block back to the free_blocks list
-            let new_free_block = FreeBlock::new(block.start, block.size);
-            self.free_blocks.push(new_free_block);
-            self.merge_free_blocks(); // Merge adjacent free blocks if possible
+            self.usage.remove(&block);
+            self.mark_freed(id, block.start, block.size);
+            self.release_range(FreeBlock::new(block.start, block.size));
+            self.retire(Handle::new(id, block.generation), Staleness::Freed);
             Ok(())
         } else {
-            Err("Block ID not found".to_string())
+            Err(self.missing(id))
+        }
+    }
+
+    /// Function to refuse changes to a block that is a page of a slab cache
+    fn check_not_slab_page(&self, id: usize) -> Result<(), MemoryError> {
+        match self.slab_owner(id) {
+            Some(cache) => Err(MemoryError::SlabPage { id, cache: cache.to_string() }),
+            None => Ok(()),
         }
     }
 
     /// Function to merge adjacent free blocks
-    /// This function will iterate through the free_blocks and merge them if they are adjacent and of the same size
-    /// It will also sort the free blocks by start address for consistent traversal
+    /// The allocation policy decides which free blocks can be merged
     pub fn merge_free_blocks(&mut self) {
-        let mut changed = true;
-    
-        while changed {
-            changed = false;
-    
-            // Sort free blocks by start address for consistent traversal
-            self.free_blocks.sort_by_key(|block| block.start);
-    
-            let mut merged = Vec::new();
-            let mut skip = std::collections::HashSet::new();
-    
-            for i in 0..self.free_blocks.len() {
-                if skip.contains(&i) {
-                    continue;
-                }
-    
-                let current = &self.free_blocks[i];
-                let mut merged_this_round = false;
-    
-                for j in (i + 1)..self.free_blocks.len() {
-                    if skip.contains(&j) {
-                        continue;
-                    }
-    
-                    let next = &self.free_blocks[j];
-    
-                    // Only merge if sizes match and buddy condition holds
-                    if current.size == next.size
-                        && (current.start ^ current.size) == next.start
-                    {
-                        let new_start = usize::min(current.start, next.start);
-                        let new_size = current.size * 2;
-                        merged.push(FreeBlock::new(new_start, new_size));
-                        skip.insert(i);
-                        skip.insert(j);
-                        changed = true;
-                        merged_this_round = true;
-                        break;
-                    }
-                }
-    
-                // If not merged, retain current block
-                if !merged_this_round && !skip.contains(&i) {
-                    merged.push(current.clone());
-                }
-            }
-    
-            self.free_blocks = merged;
-        }
+        self.policy.merge_free_blocks();
+        self.record(TraceOp::Merge, TraceResult::Done);
     }
     
     /// Function to update data in an allocated block
     /// This function will check if the new data fits in the existing block or if it needs to be reallocated
     /// If it needs to be reallocated, it will allocate a new block and copy the data over
-    pub fn update(&mut self, id: usize, new_data: &[u8]) -> Result<(), String> {
-        println!("Updating ID: {}, New Data: {:?}", id, String::from_utf8_lossy(new_data));
-    
+    /// It returns the ID of the block holding the data, which is a new ID if the block was reallocated
+    pub fn update(&mut self, id: usize, new_data: &[u8]) -> Result<usize, MemoryError> {
+        let result = self.update_block(id, new_data);
+        self.record(TraceOp::Update { id, len: new_data.len() }, self.trace_block(&result));
+        result
+    }
+
+    /// Function to update a block without recording it in the trace
+    fn update_block(&mut self, id: usize, new_data: &[u8]) -> Result<usize, MemoryError> {
+        self.check_not_slab_page(id)?;
+        let capacity = self.allocated_blocks.get(&id).map_or(0, |block| self.capacity(block));
         if let Some(block) = self.allocated_blocks.get_mut(&id) {
-            println!("Found block: start = {}, size = {}, current data size = {}", block.start, block.size, block.data_size);
-    
-            if new_data.len() > block.size {
+            if new_data.len() > capacity && self.arena.blocks.contains(&id) {
+                // Arena blocks cannot move, since they are only freed by resetting the arena
+                Err(MemoryError::DataTooLarge { data_size: new_data.len(), block_size: capacity })
+            } else if new_data.len() > capacity {
                 // If new data doesn't fit, reallocate
-                println!("New data size exceeds current block size, reallocating...");
                 let new_id = self.allocate(new_data.len())?;
                 let new_block = self.allocated_blocks.get_mut(&new_id).unwrap();
     
@@ -249,9 +352,14 @@ impl MemoryManager {
                 self.memory[new_block.start..new_block.start + new_data.len()]
                     .copy_from_slice(new_data);
                 new_block.data_size = new_data.len();
-    
-                self.delete(id)?; // Free old block
-                println!("Reallocated with new ID: {}", new_id);
+                let new_start = new_block.start;
+                self.mark_written(new_start, new_data.len());
+    
+                let generation = self.allocated_blocks[&id].generation;
+                self.free(id)?; // Free old block
+                self.usage.update_peaks();
+                self.retire(Handle::new(id, generation), Staleness::Moved { to: Handle::new(new_id, 0) });
+                Ok(new_id)
             } else {
                 // Clear existing memory region
                 let block_start = block.start;
@@ -261,38 +369,52 @@ impl MemoryManager {
                 // Write updated data
                 self.memory[block_start..block_start + new_data.len()]
                     .copy_from_slice(new_data);
+                self.usage.change_data(block.data_size, new_data.len());
                 block.data_size = new_data.len();
-    
-                println!("Data updated within existing block");
+                self.mark_written(block_start, new_data.len());
+                self.mark_cleared(block_start + new_data.len(), capacity - new_data.len());
+                Ok(id)
             }
-    
-            Ok(())
         } else {
-            Err("Block ID not found".to_string())
+            Err(self.missing(id))
         }
     }
     
     /// Function to dump the memory manager's state
-    /// This function will print the details of allocated and free blocks in a formatted manner
-    pub fn dump(&self) {
+    /// This function will return the details of allocated and free blocks in a formatted manner
+    pub fn dump(&self) -> String {
+        self.dump_as(DataFormat::Text)
+    }
+
+    /// Function to dump the memory manager's state, showing block data in the given format
+    /// JSON output describes the whole state as a single object
+    pub fn dump_as(&self, format: DataFormat) -> String {
+        if format == DataFormat::Json {
+            return self.to_json();
+        }
         let mut allocated = Vec::new();
         let mut free_blocks = Vec::new();
     
         // Collect allocated blocks
         for (id, block) in &self.allocated_blocks {
+            // Slab pages name their cache instead of showing the raw bytes of every object
+            let contents = match self.slab_owner(*id) {
+                Some(cache) => format!("Slab: {}", cache),
+                None => format!("Data: {}", display_data(&self.memory[block.start..block.start + block.data_size], format)),
+            };
             let info = format!(
-                "0x{:04X} - 0x{:04X}: ALLOCATED (ID: {}) (Size: {} bytes) Data: '{}'",
+                "0x{:04X} - 0x{:04X}: ALLOCATED (ID: {}) (Size: {} bytes) {}",
                 block.start,
                 block.start + block.size - 1,
                 id,
                 block.size,
-                String::from_utf8_lossy(&self.memory[block.start..block.start + block.data_size])
+                contents
             );
             allocated.push((block.start, info));
         }
     
         // Collect free blocks
-        for block in &self.free_blocks {
+        for block in self.policy.free_blocks() {
             let info = format!(
                 "0x{:04X} - 0x{:04X}: FREE (Size: {} bytes)",
                 block.start,
@@ -308,79 +430,260 @@ impl MemoryManager {
         // Sort allocated blocks by start address
         allocated.sort_by_key(|(start, _)| *start);
     
-        println!("Memory Dump:");
+        let mut report = String::from("Memory Dump:");
         for (_, line) in allocated {
-            println!("{}", line);
+            report.push('\n');
+            report.push_str(&line);
         }
         for (_, _, line) in free_blocks {
-            println!("{}", line);
+            report.push('\n');
+            report.push_str(&line);
+        }
+        if !self.arena.chunks.is_empty() {
+            let chunk_bytes: usize = self.arena.chunks.iter().map(|chunk| chunk.size).sum();
+            report.push_str(&format!(
+                "\nArena: {} block(s), {} of {} bytes used in {} chunk(s)",
+                self.arena.blocks.len(),
+                self.arena.get_used_bytes(),
+                chunk_bytes,
+                self.arena.chunks.len()
+            ));
         }
+        if let Some(shadow) = self.shadow.as_ref().filter(|shadow| !shadow.quarantine.is_empty()) {
+            report.push_str(&format!(
+                "\nQuarantine: {} block(s), {} bytes held back from the policy",
+                shadow.quarantine.len(),
+                shadow.quarantined_bytes()
+            ));
+        }
+        if !self.caches.is_empty() {
+            report.push_str("\nSlab caches:");
+            for stats in self.cache_stats() {
+                report.push_str("\n  ");
+                report.push_str(&slab::stats_line(&stats));
+            }
+        }
+        report
     }
     
     
 
     /// Function to execute commands from the input file (.cmmd)
-    /// This function will parse the command and call the appropriate function
-    /// It will also handle the command format and print the results
-    pub fn execute_command(&mut self, command: &str) {
-        let command = command.to_uppercase();
-        // Trim the command and remove the trailing semicolon if present
-        let command = command.trim();
-        let command = command.trim_end_matches(';');
-    
-        // Split the command into parts
-        let parts: Vec<&str> = command.split_whitespace().collect();
-    
-        // Match the command keyword and execute accordingly
-        match parts.get(0).map(|s| *s) {
-            Some("INSERT") if parts.len() > 2 => {
-                let size = parts[1].parse::<usize>().unwrap_or(0);
-                let data = parts[2..].join(" "); // Join the remaining parts to form the data string
-                if let Ok(id) = self.insert(size) {
-                    if self.set(id, data.as_bytes()).is_ok() {
-                        println!("INSERT success: ID = {}", id);
-                    } else {
-                        println!("Error storing data");
-                    }
-                } else {
-                    println!("INSERT error: No suitable block available");
+    /// This function will parse the line and execute each command found on it
+    /// A line with a single command gives that command's outcome, other lines give a batch
+    /// Commands that cannot be parsed are reported with their position in the line
+    pub fn execute_command(&mut self, command: &str) -> CommandOutcome {
+        let outcome = match parse_line(command, 1) {
+            Ok(commands) => {
+                let mut outcomes: Vec<CommandOutcome> = commands.iter().map(|command| self.run(command)).collect();
+                if outcomes.len() == 1 { outcomes.remove(0) } else { CommandOutcome::Batch(outcomes) }
+            }
+            Err(e) => CommandOutcome::Failed { command: "", error: MemoryError::InvalidCommand(e.to_string()) },
+        };
+        let outcome = self.checked(outcome);
+        self.emit(&outcome);
+        outcome
+    }
+
+    /// Function to execute a parsed command
+    /// The outcome is returned and also handed to the sink, if one is installed
+    pub fn execute(&mut self, command: &Command) -> CommandOutcome {
+        let outcome = self.run(command);
+        let outcome = self.checked(outcome);
+        self.emit(&outcome);
+        outcome
+    }
+
+    /// Function to add a failed CHECK to an outcome when the heap is checked after every command
+    /// and an invariant is broken
+    fn checked(&self, outcome: CommandOutcome) -> CommandOutcome {
+        if !self.verify_commands {
+            return outcome;
+        }
+        let check = self.verify();
+        if check.is_ok() {
+            return outcome;
+        }
+        let failed = CommandOutcome::Failed { command: "CHECK", error: MemoryError::HeapCorrupted(check.violations) };
+        match outcome {
+            CommandOutcome::Batch(mut outcomes) => {
+                outcomes.push(failed);
+                CommandOutcome::Batch(outcomes)
+            }
+            outcome => CommandOutcome::Batch(vec![outcome, failed]),
+        }
+    }
+
+    fn emit(&mut self, outcome: &CommandOutcome) {
+        if let Some(sink) = self.sink.as_mut() {
+            sink.emit(outcome);
+        }
+    }
+
+    /// Function to run a parsed command and describe what happened
+    fn run(&mut self, command: &Command) -> CommandOutcome {
+        let failed = |command, error| CommandOutcome::Failed { command, error };
+        match command {
+            Command::Insert { size, data } => {
+                match self.insert_block(*size) {
+                    Ok(id) => match self.set(id, data) {
+                        Ok(()) => CommandOutcome::Inserted { id },
+                        Err(e) => {
+                            // Give the block back so a failed insert does not leak memory
+                            let _ = self.delete(id);
+                            failed("INSERT", e)
+                        }
+                    },
+                    Err(e) => failed("INSERT", e),
                 }
             },
-            Some("READ") if parts.len() > 1 => {
-                let id = parts[1].parse::<usize>().unwrap_or(0);
-                if let Ok(details) = self.read_formatted(id) {
-                    println!("{}", details);
-                } else {
-                    println!("Error reading data");
+            Command::Read { id, format } => {
+                match self.read_formatted_as(*id, format.unwrap_or(self.default_format)) {
+                    Ok(report) => CommandOutcome::Read { id: *id, report },
+                    Err(e) => failed("READ", e),
                 }
             },
-            Some("DELETE") if parts.len() > 1 => {
-                let id = parts[1].parse::<usize>().unwrap_or(0);
-                if self.delete(id).is_ok() {
-                    println!("DELETE success: ID = {}", id);
-                } else {
-                    println!("Error deleting data");
+            Command::Delete { id } => {
+                match self.delete(*id) {
+                    Ok(()) => CommandOutcome::Deleted { id: *id },
+                    Err(e) => failed("DELETE", e),
+                }
+            },
+            Command::Update { id, data } => {
+                match self.update(*id, data) {
+                    Ok(new_id) => CommandOutcome::Updated { id: *id, new_id: Some(new_id).filter(|new_id| new_id != id) },
+                    Err(e) => failed("UPDATE", e),
+                }
+            },
+            Command::UpdateStable { id, data } => {
+                match self.update_stable(*id, data) {
+                    Ok(handle) if handle.get_generation() == 0 => CommandOutcome::Updated { id: *id, new_id: None },
+                    Ok(handle) => CommandOutcome::Moved { handle },
+                    Err(e) => failed("UPDATE", e),
                 }
             },
-            Some("UPDATE") if parts.len() > 2 => {
-                let id = parts[1].parse::<usize>().unwrap_or(0);
-                let new_data = parts[2..].join(" "); // Ensure this captures all intended data
-                if self.update(id, new_data.as_bytes()).is_ok() {
-                    println!("UPDATE success: ID = {}", id);
+            Command::Stats { format: Some(DataFormat::Json) } => CommandOutcome::Stats { report: self.stats().to_json_value().to_string() },
+            Command::Stats { .. } => CommandOutcome::Stats { report: self.stats().to_string() },
+            Command::Check => {
+                let check = self.verify();
+                if check.is_ok() {
+                    CommandOutcome::Checked { allocated_blocks: check.allocated_blocks, free_blocks: check.free_blocks }
                 } else {
-                    println!("Error updating data");
+                    failed("CHECK", MemoryError::HeapCorrupted(check.violations))
+                }
+            }
+            Command::Peek { address, len, strict } => {
+                match self.peek(*address, *len, *strict) {
+                    Ok(bytes) => CommandOutcome::Peeked { address: *address, bytes },
+                    Err(e) => failed("PEEK", e),
+                }
+            }
+            Command::Poke { address, bytes, strict } => {
+                match self.poke(*address, bytes, *strict) {
+                    Ok(()) => CommandOutcome::Poked { address: *address, len: bytes.len() },
+                    Err(e) => failed("POKE", e),
+                }
+            }
+            Command::Write { address, data, strict } => {
+                match self.poke(*address, data, *strict) {
+                    Ok(()) => CommandOutcome::Written { address: *address, len: data.len() },
+                    Err(e) => failed("WRITE", e),
+                }
+            }
+            Command::Whois { address } => {
+                match self.whois(*address) {
+                    Ok(whois) => CommandOutcome::Located(whois),
+                    Err(e) => failed("WHOIS", e),
+                }
+            }
+            Command::Hexdump { range } => {
+                let (start, len) = range.unwrap_or((0, self.config.get_heap_size()));
+                match self.hexdump(start, len) {
+                    Ok(report) => CommandOutcome::Dumped { report },
+                    Err(e) => failed("HEXDUMP", e),
+                }
+            }
+            Command::Map => CommandOutcome::Dumped { report: self.memory_map() },
+            Command::Dump { format } => CommandOutcome::Dumped { report: self.dump_as(format.unwrap_or(self.default_format)) },
+            Command::ArenaInsert { size, data } => {
+                match self.arena_allocate(*size) {
+                    Ok(id) => match self.store(id, data) {
+                        Ok(()) => CommandOutcome::ArenaInserted { id },
+                        Err(e) => {
+                            // The block is the last one of the arena, so resetting to it gives the room back
+                            let _ = self.arena_reset(self.arena_mark() - 1);
+                            failed("ARENA INSERT", e)
+                        }
+                    },
+                    Err(e) => failed("ARENA INSERT", e),
+                }
+            },
+            Command::ArenaMark => CommandOutcome::ArenaMarked { mark: self.arena_mark() },
+            Command::ArenaReset { mark } => {
+                match self.arena_reset(*mark) {
+                    Ok(freed) => CommandOutcome::ArenaReset { mark: *mark, freed },
+                    Err(e) => failed("ARENA RESET", e),
+                }
+            },
+            Command::CacheCreate { name, object_size } => {
+                match self.cache_create(name, *object_size) {
+                    Ok(()) => CommandOutcome::CacheCreated { name: name.clone(), object_size: *object_size },
+                    Err(e) => failed("CACHE CREATE", e),
+                }
+            },
+            Command::CacheAlloc { name } => {
+                match self.cache_alloc(name) {
+                    Ok(id) => {
+                        let address = self.cache_object_address(name, id).unwrap();
+                        CommandOutcome::CacheAllocated { name: name.clone(), id, address }
+                    }
+                    Err(e) => failed("CACHE ALLOC", e),
+                }
+            },
+            Command::CacheFree { name, id } => {
+                match self.cache_free(name, *id) {
+                    Ok(()) => CommandOutcome::CacheFreed { name: name.clone(), id: *id },
+                    Err(e) => failed("CACHE FREE", e),
+                }
+            },
+            Command::Policy { name } => {
+                match self.set_policy(name) {
+                    Ok(()) => CommandOutcome::PolicyChanged { name: self.policy.name() },
+                    Err(e) => failed("POLICY", e),
                 }
             },
-            
-            Some("DUMP") => {
-                self.dump();
+            Command::Save { path } => {
+                // Snapshots only know free and allocated blocks, so the quarantine is emptied first
+                self.flush_quarantine();
+                match self.save_snapshot(path) {
+                    Ok(()) => CommandOutcome::Saved { path: path.clone() },
+                    Err(e) => failed("SAVE", e),
+                }
             },
-            Some("EXIT") => {
-                println!("Exiting...");
-                std::process::exit(0);
+            Command::Load { path } => {
+                match self.load_snapshot(path) {
+                    Ok(()) => CommandOutcome::Loaded { path: path.clone() },
+                    Err(e) => failed("LOAD", e),
+                }
             },
-            _ => println!("Unknown or invalid command"),
+            Command::Exit => CommandOutcome::Exit,
         }
     }
     
 }
+
+/// Function to show block data in READ and DUMP output
+/// Text is quoted, while hex is left bare so it can be parsed again
+fn display_data(data: &[u8], format: DataFormat) -> String {
+    match format {
+        DataFormat::Text => format!("'{}'", format_data(data, format)),
+        DataFormat::Hex | DataFormat::Json => format_data(data, format),
+    }
+}
+
+/// Implement Default for MemoryManager
+impl Default for MemoryManager {
+    fn default() -> Self {
+        MemoryManager::new()
+    }
+}
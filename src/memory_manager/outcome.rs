@@ -0,0 +1,141 @@
+use std::fmt;
+
+use super::error::MemoryError;
+use super::handle::Handle;
+use super::raw::Whois;
+
+/// CommandOutcome is the result of executing a command on the memory manager.
+/// Its Display implementation gives the human-readable message for the command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandOutcome {
+    /// A block was allocated and its data stored.
+    Inserted { id: usize },
+    /// A block was read and rendered in the requested format.
+    Read { id: usize, report: String },
+    /// The data of a block was replaced. `new_id` is set when the block had to be reallocated.
+    Updated { id: usize, new_id: Option<usize> },
+    /// The data of a block was replaced by UPDATE STABLE, which moved the block under the same ID.
+    Moved { handle: Handle },
+    /// A block was freed.
+    Deleted { id: usize },
+    /// The state of the manager was rendered in the requested format.
+    Dumped { report: String },
+    /// Raw bytes of the heap were read by PEEK.
+    Peeked { address: usize, bytes: Vec<u8> },
+    /// Byte values were written to the heap by POKE.
+    Poked { address: usize, len: usize },
+    /// Data was written to the heap by WRITE.
+    Written { address: usize, len: usize },
+    /// WHOIS found the block holding an address.
+    Located(Whois),
+    /// A block was allocated in the arena and its data stored.
+    ArenaInserted { id: usize },
+    /// The current mark of the arena was taken.
+    ArenaMarked { mark: usize },
+    /// The arena blocks allocated after a mark were freed.
+    ArenaReset { mark: usize, freed: usize },
+    /// A slab cache was created.
+    CacheCreated { name: String, object_size: usize },
+    /// An object was allocated from a slab cache.
+    CacheAllocated { name: String, id: usize, address: usize },
+    /// An object was given back to its slab cache.
+    CacheFreed { name: String, id: usize },
+    /// The allocation policy was replaced.
+    PolicyChanged { name: &'static str },
+    /// A snapshot of the manager was written to a file.
+    Saved { path: String },
+    /// The manager state was replaced by a snapshot read from a file.
+    Loaded { path: String },
+    /// The heap statistics were rendered in the requested format.
+    Stats { report: String },
+    /// The heap passed CHECK.
+    Checked { allocated_blocks: usize, free_blocks: usize },
+    /// The script asked to stop.
+    Exit,
+    /// The outcomes of a line holding zero or several commands.
+    Batch(Vec<CommandOutcome>),
+    /// The command failed. `command` is the keyword of the command that failed.
+    Failed { command: &'static str, error: MemoryError },
+}
+
+/// Implement CommandOutcome struct
+impl CommandOutcome {
+    /// Returns the first error found in the outcome, if any
+    pub fn error(&self) -> Option<&MemoryError> {
+        match self {
+            CommandOutcome::Failed { error, .. } => Some(error),
+            CommandOutcome::Batch(outcomes) => outcomes.iter().find_map(CommandOutcome::error),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the outcome, or any outcome of a batch, asks to stop
+    pub fn is_exit(&self) -> bool {
+        match self {
+            CommandOutcome::Exit => true,
+            CommandOutcome::Batch(outcomes) => outcomes.iter().any(CommandOutcome::is_exit),
+            _ => false,
+        }
+    }
+}
+
+/// Implement Display for CommandOutcome
+impl fmt::Display for CommandOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandOutcome::Inserted { id } => write!(f, "INSERT success: ID = {}", id),
+            CommandOutcome::Read { report, .. } => write!(f, "{}", report),
+            CommandOutcome::Updated { id, new_id: None } => write!(f, "UPDATE success: ID = {}", id),
+            CommandOutcome::Updated { id, new_id: Some(new_id) } => {
+                write!(f, "UPDATE success: ID = {}, reallocated with new ID: {}", id, new_id)
+            }
+            CommandOutcome::Moved { handle } => {
+                write!(f, "UPDATE success: ID = {}, reallocated as {}", handle.get_id(), handle)
+            }
+            CommandOutcome::Deleted { id } => write!(f, "DELETE success: ID = {}", id),
+            CommandOutcome::Dumped { report } => write!(f, "{}", report),
+            CommandOutcome::Peeked { address, bytes } => {
+                let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                let text: String =
+                    bytes.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }).collect();
+                write!(f, "PEEK success: {} byte(s) at 0x{:04X}: {} |{}|", bytes.len(), address, hex.join(" "), text)
+            }
+            CommandOutcome::Poked { address, len } => write!(f, "POKE success: {} byte(s) written at 0x{:04X}", len, address),
+            CommandOutcome::Written { address, len } => write!(f, "WRITE success: {} byte(s) written at 0x{:04X}", len, address),
+            CommandOutcome::Located(whois) => write!(f, "WHOIS success: {}", whois),
+            CommandOutcome::ArenaInserted { id } => write!(f, "ARENA INSERT success: ID = {}", id),
+            CommandOutcome::ArenaMarked { mark } => write!(f, "ARENA MARK success: mark = {}", mark),
+            CommandOutcome::ArenaReset { mark, freed } => {
+                write!(f, "ARENA RESET success: mark = {}, freed {} block(s)", mark, freed)
+            }
+            CommandOutcome::CacheCreated { name, object_size } => {
+                write!(f, "CACHE CREATE success: {} (Object size: {} bytes)", name, object_size)
+            }
+            CommandOutcome::CacheAllocated { name, id, address } => {
+                write!(f, "CACHE ALLOC success: {} ID = {}, Address: 0x{:04X}", name, id, address)
+            }
+            CommandOutcome::CacheFreed { name, id } => write!(f, "CACHE FREE success: {} ID = {}", name, id),
+            CommandOutcome::PolicyChanged { name } => write!(f, "POLICY success: {}", name),
+            CommandOutcome::Saved { path } => write!(f, "SAVE success: {}", path),
+            CommandOutcome::Loaded { path } => write!(f, "LOAD success: {}", path),
+            CommandOutcome::Stats { report } => write!(f, "{}", report),
+            CommandOutcome::Checked { allocated_blocks, free_blocks } => write!(
+                f,
+                "CHECK success: {} allocated and {} free block(s), no violations",
+                allocated_blocks, free_blocks
+            ),
+            CommandOutcome::Exit => write!(f, "Exiting..."),
+            CommandOutcome::Batch(outcomes) => {
+                for (i, outcome) in outcomes.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", outcome)?;
+                }
+                Ok(())
+            }
+            CommandOutcome::Failed { command: "", error } => write!(f, "{}", error),
+            CommandOutcome::Failed { command, error } => write!(f, "{} error: {}", command, error),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+
+use super::buddy::BuddyPolicy;
+use super::buddy_bitmap::BitmapBuddyPolicy;
+use super::config::MemoryConfig;
+use super::error::MemoryError;
+use super::fit::{FitPolicy, FitStrategy};
+use super::free_block::FreeBlock;
+use super::tlsf::TlsfPolicy;
+
+/// AllocationPolicy decides where blocks are placed inside the heap.
+/// The memory manager owns the memory array, the allocated blocks and the IDs, while the policy
+/// only keeps track of the free space and how it is split and merged.
+pub trait AllocationPolicy {
+    /// Returns the name of the policy, as used on the command line.
+    fn name(&self) -> &'static str;
+
+    /// Returns the size of the block that would be handed out for a request of `requested` bytes.
+    fn block_size(&self, requested: usize) -> usize;
+
+    /// Carves a block large enough for `requested` bytes out of the free space.
+    /// The returned block is marked as not free. Returns None if no free block is large enough.
+    fn allocate(&mut self, requested: usize) -> Option<FreeBlock>;
+
+    /// Gives a block previously returned by `allocate` back to the free space.
+    fn release(&mut self, block: FreeBlock);
+
+    /// Merges adjacent free blocks where the policy allows it.
+    fn merge_free_blocks(&mut self);
+
+    /// Returns a copy of the free blocks tracked by the policy.
+    fn free_blocks(&self) -> Vec<FreeBlock>;
+
+    /// Replaces the free blocks tracked by the policy, e.g. when a snapshot is loaded.
+    /// Returns an error message if the blocks cannot be managed by the policy.
+    fn restore_free_blocks(&mut self, free_blocks: &[FreeBlock]) -> Result<(), String>;
+
+    /// Returns the number of operations the policy performed so far.
+    /// Policies that do not count their work report zero for everything.
+    fn counters(&self) -> PolicyCounters {
+        PolicyCounters::default()
+    }
+
+    /// Checks the invariants of the policy, given the blocks currently allocated from it.
+    /// Returns a description of every violation found. Policies that check nothing find none.
+    fn verify(&self, _allocated: &[FreeBlock]) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// PolicyCounters counts the work done by an allocation policy, to compare policies on the same workload.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PolicyCounters {
+    pub allocations: usize,        // Successful calls to allocate
+    pub failed_allocations: usize, // Calls to allocate that found no block
+    pub releases: usize,           // Calls to release
+    pub splits: usize,             // Free blocks split in two while allocating
+    pub merges: usize,             // Free blocks merged with a neighbour or buddy
+    pub search_steps: usize,       // Free lists, ranges or bitmap words examined while allocating
+}
+
+/// Names of the policies that can be created with `create_policy`
+pub const POLICY_NAMES: &[&str] = &["buddy", "buddy-bitmap", "best-fit", "worst-fit", "next-fit", "tlsf"];
+
+/// Function to create an allocation policy from its name, for a validated configuration
+pub fn create_policy(name: &str, config: &MemoryConfig) -> Result<Box<dyn AllocationPolicy>, MemoryError> {
+    match name {
+        "buddy" => Ok(Box::new(BuddyPolicy::with_config(config))),
+        "buddy-bitmap" => Ok(Box::new(BitmapBuddyPolicy::with_config(config))),
+        "best-fit" => Ok(Box::new(FitPolicy::with_config(FitStrategy::BestFit, config))),
+        "worst-fit" => Ok(Box::new(FitPolicy::with_config(FitStrategy::WorstFit, config))),
+        "next-fit" => Ok(Box::new(FitPolicy::with_config(FitStrategy::NextFit, config))),
+        "tlsf" => Ok(Box::new(TlsfPolicy::with_config(config))),
+        _ => Err(MemoryError::UnknownPolicy(name.to_string())),
+    }
+}
+
+/// Function to describe a block in a violation found by `verify`
+fn describe(block: &FreeBlock, free: bool) -> String {
+    let kind = if free { "free block" } else { "allocated block" };
+    format!("{} at 0x{:04X} ({} bytes)", kind, block.start, block.size)
+}
+
+/// Function to check that blocks are buddy blocks: a power of two between the minimum and maximum
+/// block size and aligned to their size, with no two free buddies left unmerged
+pub(crate) fn verify_buddy_blocks(
+    free: &[FreeBlock],
+    allocated: &[FreeBlock],
+    min_block_size: usize,
+    max_block_size: usize,
+) -> Vec<String> {
+    let mut violations = Vec::new();
+    let blocks = free.iter().map(|block| (block, true)).chain(allocated.iter().map(|block| (block, false)));
+    for (block, is_free) in blocks {
+        if !block.size.is_power_of_two() || block.size < min_block_size || block.size > max_block_size {
+            violations.push(format!("{} is not a valid buddy block size", describe(block, is_free)));
+        } else if !block.start.is_multiple_of(block.size) {
+            violations.push(format!("{} is not aligned to its size", describe(block, is_free)));
+        }
+    }
+
+    let free_set: HashSet<(usize, usize)> = free.iter().map(|block| (block.start, block.size)).collect();
+    for block in free {
+        let buddy = block.start ^ block.size;
+        if block.size < max_block_size && block.start < buddy && free_set.contains(&(buddy, block.size)) {
+            violations.push(format!(
+                "free blocks at 0x{:04X} and 0x{:04X} ({} bytes) are buddies but were not merged",
+                block.start, buddy, block.size
+            ));
+        }
+    }
+    violations
+}
+
+/// Function to check that blocks are multiples of the minimum block size, aligned to it, and that
+/// no two free blocks touch, since policies that merge on release never leave them side by side
+pub(crate) fn verify_coalesced_blocks(free: &[FreeBlock], allocated: &[FreeBlock], min_block_size: usize) -> Vec<String> {
+    let mut violations = Vec::new();
+    let blocks = free.iter().map(|block| (block, true)).chain(allocated.iter().map(|block| (block, false)));
+    for (block, is_free) in blocks {
+        if block.size == 0 || !block.size.is_multiple_of(min_block_size) || !block.start.is_multiple_of(min_block_size) {
+            violations.push(format!("{} is not aligned to the minimum block size", describe(block, is_free)));
+        }
+    }
+
+    let mut sorted: Vec<&FreeBlock> = free.iter().collect();
+    sorted.sort_by_key(|block| block.start);
+    for pair in sorted.windows(2) {
+        if pair[0].start + pair[0].size == pair[1].start {
+            violations.push(format!(
+                "free blocks at 0x{:04X} and 0x{:04X} touch but were not merged",
+                pair[0].start, pair[1].start
+            ));
+        }
+    }
+    violations
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+
+use super::MemoryManager;
+use super::error::MemoryError;
+use super::shadow::ShadowState;
+
+/// Owner is the part of the heap an address belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Owner {
+    /// An allocated block, including arena blocks and slab pages.
+    Allocated { id: usize, start: usize, size: usize },
+    /// A free block of the allocation policy.
+    Free { start: usize, size: usize },
+    /// The part of an arena chunk that no arena block uses yet.
+    ArenaChunk { start: usize, size: usize },
+    /// A freed block held back from the allocation policy by the quarantine of the shadow memory.
+    Quarantined { start: usize, size: usize },
+}
+
+/// Implement Owner enum
+impl Owner {
+    /// Returns the first address and the size of the region
+    pub fn range(&self) -> (usize, usize) {
+        match *self {
+            Owner::Allocated { start, size, .. }
+            | Owner::Free { start, size }
+            | Owner::ArenaChunk { start, size }
+            | Owner::Quarantined { start, size } => (start, size),
+        }
+    }
+}
+
+/// Implement Display for Owner
+/// Owners are named briefly, without their range
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Owner::Allocated { id, .. } => write!(f, "allocated block {}", id),
+            Owner::Free { .. } => write!(f, "free block"),
+            Owner::ArenaChunk { .. } => write!(f, "unused part of an arena chunk"),
+            Owner::Quarantined { .. } => write!(f, "quarantined block"),
+        }
+    }
+}
+
+/// Whois describes which block holds an address, as found by `whois`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Whois {
+    pub address: usize,
+    pub owner: Owner,
+    pub state: Option<ShadowState>, // State of the byte when shadow memory is enabled
+}
+
+/// Implement Display for Whois
+impl fmt::Display for Whois {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (start, size) = self.owner.range();
+        write!(
+            f,
+            "0x{:04X} is in {} (0x{:04X}-0x{:04X}, {} bytes), {} bytes from its start",
+            self.address,
+            self.owner,
+            start,
+            start + size - 1,
+            size,
+            self.address - start
+        )?;
+        if let Some(state) = self.state {
+            write!(f, ", shadow state: {}", state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Raw access of the memory manager
+/// PEEK, POKE and WRITE work on addresses instead of block IDs, so they can reach any byte of the
+/// heap. In strict mode they refuse bytes that are not all inside a single allocated block.
+impl MemoryManager {
+    /// Function to read `len` bytes of the heap from `address`
+    pub fn peek(&self, address: usize, len: usize, strict: bool) -> Result<Vec<u8>, MemoryError> {
+        if strict {
+            self.check_inside_block(address, len)?;
+        }
+        self.read_raw(address, len)
+    }
+
+    /// Function to write bytes to the heap from `address`
+    /// The data size of the blocks written to is left as it is
+    pub fn poke(&mut self, address: usize, bytes: &[u8], strict: bool) -> Result<(), MemoryError> {
+        if strict {
+            self.check_inside_block(address, bytes.len())?;
+        }
+        self.write_raw(address, bytes)
+    }
+
+    /// Function to find which block holds an address
+    pub fn whois(&self, address: usize) -> Result<Whois, MemoryError> {
+        let heap_size = self.config.get_heap_size();
+        if address >= heap_size {
+            return Err(MemoryError::AddressOutOfRange { address, len: 1, heap_size });
+        }
+        let owner = self.regions().into_iter().find(|owner| {
+            let (start, size) = owner.range();
+            (start..start + size).contains(&address)
+        });
+        match owner {
+            Some(owner) => Ok(Whois { address, owner, state: self.shadow_state(address) }),
+            None => Err(MemoryError::HeapCorrupted(vec![format!("0x{:04X} is in no block", address)])),
+        }
+    }
+
+    /// Function to list the regions of the heap ordered by address: allocated blocks, free blocks,
+    /// the unused parts of arena chunks and quarantined blocks
+    pub(crate) fn regions(&self) -> Vec<Owner> {
+        let mut regions: Vec<Owner> = self
+            .allocated_blocks
+            .values()
+            .map(|block| Owner::Allocated { id: block.id, start: block.start, size: block.size })
+            .collect();
+        regions.extend(self.policy.free_blocks().iter().map(|block| Owner::Free { start: block.start, size: block.size }));
+        regions.extend(
+            self.arena
+                .chunks
+                .iter()
+                .filter(|chunk| chunk.used < chunk.size)
+                .map(|chunk| Owner::ArenaChunk { start: chunk.start + chunk.used, size: chunk.size - chunk.used }),
+        );
+        if let Some(shadow) = &self.shadow {
+            regions.extend(shadow.quarantine.iter().map(|block| Owner::Quarantined { start: block.start, size: block.size }));
+        }
+        regions.sort_by_key(|owner| owner.range());
+        regions
+    }
+
+    /// Function to check that `len` bytes from `address` lie inside a single allocated block
+    fn check_inside_block(&self, address: usize, len: usize) -> Result<(), MemoryError> {
+        let block = self.allocated_blocks.values().find(|block| (block.start..block.start + block.size).contains(&address));
+        match block {
+            Some(block) if address.checked_add(len).is_some_and(|end| end <= block.start + block.size) => Ok(()),
+            block => Err(MemoryError::OutsideBlock { address, len, block: block.map(|block| block.id) }),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::json::JsonValue;
+
+use super::MemoryManager;
+use super::allocated_block::AllocatedBlock;
+use super::error::MemoryError;
+
+/// Function to write bytes as a string of hex digits
+fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Serialization of the memory manager state into JSON
+impl MemoryManager {
+    /// Function to describe an allocated block and its data as JSON
+    fn block_json(&self, block: &AllocatedBlock) -> JsonValue {
+        let data = &self.memory[block.start..block.start + block.data_size];
+        JsonValue::object([
+            ("id", block.id.into()),
+            ("start", block.start.into()),
+            ("end", (block.start + block.size - 1).into()),
+            ("size", block.size.into()),
+            ("data_size", block.data_size.into()),
+            ("data", String::from_utf8_lossy(data).into_owned().into()),
+            ("data_hex", hex_string(data).into()),
+        ])
+    }
+
+    /// Function to describe the block with the given ID as a JSON string
+    pub fn read_json(&self, id: usize) -> Result<String, MemoryError> {
+        match self.allocated_blocks.get(&id) {
+            Some(block) => Ok(self.block_json(block).to_string()),
+            None => Err(self.missing(id)),
+        }
+    }
+
+    /// Function to describe the operation counters of the policy as JSON
+    fn operations_json(&self) -> JsonValue {
+        let counters = self.policy.counters();
+        JsonValue::object([
+            ("allocations", counters.allocations.into()),
+            ("failed_allocations", counters.failed_allocations.into()),
+            ("releases", counters.releases.into()),
+            ("splits", counters.splits.into()),
+            ("merges", counters.merges.into()),
+            ("search_steps", counters.search_steps.into()),
+        ])
+    }
+
+    /// Function to describe the whole state of the manager as JSON
+    /// Allocated and free blocks are listed by start address
+    pub fn to_json_value(&self) -> JsonValue {
+        let mut allocated: Vec<&AllocatedBlock> = self.allocated_blocks.values().collect();
+        allocated.sort_by_key(|block| block.start);
+        let mut free_blocks = self.policy.free_blocks();
+        free_blocks.sort_by_key(|block| block.start);
+
+        let allocated_bytes: usize = allocated.iter().map(|block| block.size).sum();
+        let data_bytes: usize = allocated.iter().map(|block| block.data_size).sum();
+        let free_bytes: usize = free_blocks.iter().map(|block| block.size).sum();
+
+        JsonValue::object([
+            (
+                "config",
+                JsonValue::object([
+                    ("heap_size", self.config.get_heap_size().into()),
+                    ("min_block_size", self.config.get_min_block_size().into()),
+                    ("max_order", self.config.get_max_order().into()),
+                ]),
+            ),
+            ("policy", self.policy.name().into()),
+            (
+                "counters",
+                JsonValue::object([
+                    ("next_id", self.next_id.into()),
+                    ("allocated_blocks", allocated.len().into()),
+                    ("allocated_bytes", allocated_bytes.into()),
+                    ("data_bytes", data_bytes.into()),
+                    ("free_blocks", free_blocks.len().into()),
+                    ("free_bytes", free_bytes.into()),
+                    ("largest_free_block", self.largest_free_block().into()),
+                    ("operations", self.operations_json()),
+                ]),
+            ),
+            (
+                "allocated_blocks",
+                JsonValue::Array(allocated.iter().map(|block| self.block_json(block)).collect()),
+            ),
+            (
+                "free_blocks",
+                JsonValue::Array(
+                    free_blocks
+                        .iter()
+                        .map(|block| {
+                            JsonValue::object([
+                                ("start", block.start.into()),
+                                ("end", (block.start + block.size - 1).into()),
+                                ("size", block.size.into()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "caches",
+                JsonValue::Array(
+                    self.cache_stats()
+                        .into_iter()
+                        .map(|stats| {
+                            JsonValue::object([
+                                ("name", stats.name.into()),
+                                ("object_size", stats.object_size.into()),
+                                ("full_slabs", stats.full_slabs.into()),
+                                ("partial_slabs", stats.partial_slabs.into()),
+                                ("empty_slabs", stats.empty_slabs.into()),
+                                ("objects_in_use", stats.objects_in_use.into()),
+                                ("total_objects", stats.total_objects.into()),
+                                ("page_bytes", stats.page_bytes.into()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "arena",
+                JsonValue::object([
+                    ("blocks", self.arena.get_block_count().into()),
+                    ("used_bytes", self.arena.get_used_bytes().into()),
+                    (
+                        "chunks",
+                        JsonValue::Array(
+                            self.arena
+                                .get_chunks()
+                                .iter()
+                                .map(|chunk| {
+                                    JsonValue::object([
+                                        ("start", chunk.start.into()),
+                                        ("size", chunk.size.into()),
+                                        ("used", chunk.used.into()),
+                                    ])
+                                })
+                                .collect(),
+                        ),
+                    ),
+                ]),
+            ),
+        ])
+    }
+
+    /// Function to describe the whole state of the manager as a JSON string
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use super::MemoryManager;
+use super::allocated_block::AllocatedBlock;
+use super::error::MemoryError;
+use super::free_block::FreeBlock;
+use super::trace::{TraceOp, TraceResult};
+
+/// Default number of bytes of redzone kept after every block
+pub const DEFAULT_REDZONE: usize = 16;
+
+/// Default number of freed bytes held back from the allocation policy
+pub const DEFAULT_QUARANTINE: usize = 4096;
+
+/// ShadowState is what the shadow memory knows about one byte of the heap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadowState {
+    /// The byte is not part of any block.
+    Unallocated,
+    /// The byte belongs to a block and holds data written to it.
+    Addressable,
+    /// The byte belongs to a block but nothing was written to it yet.
+    Uninitialized,
+    /// The byte is part of the poisoned area kept after a block to catch overflows.
+    Redzone,
+    /// The byte belonged to a block that was freed, and was not handed out again since.
+    Freed,
+}
+
+/// Implement Display for ShadowState
+impl fmt::Display for ShadowState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShadowState::Unallocated => write!(f, "unallocated"),
+            ShadowState::Addressable => write!(f, "addressable"),
+            ShadowState::Uninitialized => write!(f, "uninitialized"),
+            ShadowState::Redzone => write!(f, "redzone"),
+            ShadowState::Freed => write!(f, "freed"),
+        }
+    }
+}
+
+/// ShadowConfig describes the redzones and the quarantine of the shadow memory.
+/// Every block gets at least `redzone` poisoned bytes after its data, and freed blocks stay out of
+/// the allocation policy until more than `quarantine` bytes are waiting behind them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShadowConfig {
+    redzone: usize,
+    quarantine: usize,
+}
+
+/// Implement ShadowConfig struct
+impl ShadowConfig {
+    /// Creates the default configuration: 16-byte redzones and a 4 KiB quarantine.
+    pub fn new() -> Self {
+        ShadowConfig { redzone: DEFAULT_REDZONE, quarantine: DEFAULT_QUARANTINE }
+    }
+
+    /// Sets the number of bytes of redzone after every block.
+    pub fn with_redzone(mut self, redzone: usize) -> Self {
+        self.redzone = redzone;
+        self
+    }
+
+    /// Sets the number of freed bytes held back from the allocation policy.
+    pub fn with_quarantine(mut self, quarantine: usize) -> Self {
+        self.quarantine = quarantine;
+        self
+    }
+
+    /// Returns the redzone field of the configuration.
+    pub fn get_redzone(&self) -> usize {
+        self.redzone
+    }
+
+    /// Returns the quarantine field of the configuration.
+    pub fn get_quarantine(&self) -> usize {
+        self.quarantine
+    }
+}
+
+/// Implement Default for ShadowConfig
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        ShadowConfig::new()
+    }
+}
+
+/// AccessError is the kind of memory-safety bug found by a raw access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessError {
+    /// The access reached a redzone or memory outside every block.
+    HeapBufferOverflow,
+    /// The access reached a freed block.
+    UseAfterFree,
+    /// A read reached bytes of a block that were never written.
+    UninitializedRead,
+}
+
+/// Implement Display for AccessError
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccessError::HeapBufferOverflow => write!(f, "heap-buffer-overflow"),
+            AccessError::UseAfterFree => write!(f, "heap-use-after-free"),
+            AccessError::UninitializedRead => write!(f, "uninitialized-read"),
+        }
+    }
+}
+
+/// BadAccess is a raw access refused by the shadow memory, described the way AddressSanitizer does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BadAccess {
+    pub kind: AccessError,
+    pub address: usize,       // First byte of the access that is not allowed
+    pub size: usize,          // Number of bytes the access asked for
+    pub write: bool,
+    pub block: Option<usize>, // ID of the block owning the address, or the nearest block before it
+    pub location: String,     // Where the address lies relative to that block
+}
+
+/// Implement Display for BadAccess
+impl fmt::Display for BadAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} on address 0x{:04X}: {} of size {}; {}",
+            self.kind,
+            self.address,
+            if self.write { "WRITE" } else { "READ" },
+            self.size,
+            self.location
+        )
+    }
+}
+
+/// Shadow is one state per byte of the heap, with the freed blocks still remembered and the
+/// quarantine of ranges not yet given back to the allocation policy.
+#[derive(Clone, Debug)]
+pub(crate) struct Shadow {
+    pub(crate) config: ShadowConfig,
+    states: Vec<ShadowState>,
+    freed: BTreeMap<usize, (usize, usize)>, // Start of each freed block still poisoned, with its ID and size
+    pub(crate) quarantine: VecDeque<FreeBlock>, // Ranges held back from the policy, oldest first
+}
+
+/// Implement Shadow struct
+impl Shadow {
+    /// Creates the shadow of a heap of `heap_size` bytes, all of them unallocated
+    pub(crate) fn new(config: ShadowConfig, heap_size: usize) -> Self {
+        Shadow { config, states: vec![ShadowState::Unallocated; heap_size], freed: BTreeMap::new(), quarantine: VecDeque::new() }
+    }
+
+    /// Function to set the state of `len` bytes from `start`
+    fn mark(&mut self, start: usize, len: usize, state: ShadowState) {
+        self.states[start..start + len].fill(state);
+    }
+
+    /// Function to forget the freed blocks that overlap a range handed out again
+    fn forget_freed(&mut self, start: usize, len: usize) {
+        let overlapping: Vec<usize> = self
+            .freed
+            .range(..start + len)
+            .filter(|&(&freed_start, &(_, size))| freed_start + size > start)
+            .map(|(&freed_start, _)| freed_start)
+            .collect();
+        for freed_start in overlapping {
+            self.freed.remove(&freed_start);
+        }
+    }
+
+    /// Returns the number of bytes waiting in the quarantine
+    pub(crate) fn quarantined_bytes(&self) -> usize {
+        self.quarantine.iter().map(|block| block.size).sum()
+    }
+}
+
+/// Shadow memory of the memory manager
+/// With shadow memory enabled, blocks are placed with a redzone after them, freed blocks wait in a
+/// quarantine before the policy may hand them out again, and raw accesses are checked byte by byte
+/// the way AddressSanitizer checks loads and stores.
+impl MemoryManager {
+    /// Function to turn on shadow memory with the given redzones and quarantine
+    /// It can only be done while no block is allocated, since existing blocks have no redzones
+    pub fn enable_shadow(&mut self, config: ShadowConfig) -> Result<(), MemoryError> {
+        if !self.allocated_blocks.is_empty() {
+            return Err(MemoryError::InvalidCommand(format!(
+                "cannot enable shadow memory while {} block(s) are allocated",
+                self.allocated_blocks.len()
+            )));
+        }
+        if self.trace.is_some() {
+            return Err(MemoryError::InvalidCommand("cannot enable shadow memory while a trace is recorded".to_string()));
+        }
+        self.release_quarantine();
+        self.shadow = Some(Shadow::new(config, self.config.get_heap_size()));
+        Ok(())
+    }
+
+    /// Returns the configuration of the shadow memory, or None if it is not enabled
+    pub fn shadow_config(&self) -> Option<ShadowConfig> {
+        self.shadow.as_ref().map(|shadow| shadow.config)
+    }
+
+    /// Returns the shadow state of the byte at `address`, or None without shadow memory
+    pub fn shadow_state(&self, address: usize) -> Option<ShadowState> {
+        self.shadow.as_ref().and_then(|shadow| shadow.states.get(address).copied())
+    }
+
+    /// Function to read `len` bytes of the heap from `address`, whatever blocks they belong to
+    /// With shadow memory, reading a redzone, a freed block or unwritten bytes is refused
+    pub fn read_raw(&self, address: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        self.check_access(address, len, false)?;
+        Ok(self.memory[address..address + len].to_vec())
+    }
+
+    /// Function to write bytes to the heap from `address`, whatever blocks they belong to
+    /// With shadow memory, writing a redzone or a freed block is refused, and the bytes written
+    /// become addressable
+    pub fn write_raw(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        self.check_access(address, bytes.len(), true)?;
+        self.memory[address..address + bytes.len()].copy_from_slice(bytes);
+        self.mark_written(address, bytes.len());
+        Ok(())
+    }
+
+    /// Function to give every range of the quarantine back to the allocation policy
+    /// The bytes stay poisoned as freed until they are handed out again
+    pub fn flush_quarantine(&mut self) {
+        if self.shadow.is_some() {
+            self.release_quarantine();
+            self.record(TraceOp::FlushQuarantine, TraceResult::Done);
+        }
+    }
+
+    /// Function to give the quarantined ranges back to the allocation policy without recording it in the trace
+    fn release_quarantine(&mut self) {
+        let Some(shadow) = self.shadow.as_mut() else {
+            return;
+        };
+        for block in shadow.quarantine.drain(..) {
+            self.policy.release(block);
+        }
+    }
+
+    /// Function to check that a raw access stays inside the heap and, with shadow memory, only
+    /// touches bytes it may
+    pub(crate) fn check_access(&self, address: usize, len: usize, write: bool) -> Result<(), MemoryError> {
+        let heap_size = self.config.get_heap_size();
+        if address.checked_add(len).is_none_or(|end| end > heap_size) {
+            return Err(MemoryError::AddressOutOfRange { address, len, heap_size });
+        }
+        let Some(shadow) = &self.shadow else {
+            return Ok(());
+        };
+        for (offset, &state) in shadow.states[address..address + len].iter().enumerate() {
+            let kind = match state {
+                ShadowState::Addressable => continue,
+                ShadowState::Uninitialized if write => continue,
+                ShadowState::Uninitialized => AccessError::UninitializedRead,
+                ShadowState::Freed => AccessError::UseAfterFree,
+                ShadowState::Redzone | ShadowState::Unallocated => AccessError::HeapBufferOverflow,
+            };
+            let (block, location) = self.locate(address + offset, state);
+            return Err(MemoryError::BadAccess(BadAccess { kind, address: address + offset, size: len, write, block, location }));
+        }
+        Ok(())
+    }
+
+    /// Function to describe where a bad address lies, returning the ID of the block it is reported against
+    fn locate(&self, address: usize, state: ShadowState) -> (Option<usize>, String) {
+        let region = |block: &AllocatedBlock, freed: bool| {
+            let size = if freed { block.size } else { self.capacity(block) };
+            format!("{}-byte region of {}block {} [0x{:04X}, 0x{:04X})", size, if freed { "freed " } else { "" }, block.id, block.start, block.start + size)
+        };
+        let freed = self.shadow.as_ref().and_then(|shadow| {
+            let (&start, &(id, size)) = shadow.freed.range(..=address).rev().find(|&(&start, &(_, size))| address < start + size)?;
+            Some(AllocatedBlock::new(start, size, id, 0))
+        });
+        if state == ShadowState::Freed
+            && let Some(block) = freed
+        {
+            return (Some(block.id), format!("0x{:04X} is located {} bytes inside the {}", address, address - block.start, region(&block, true)));
+        }
+
+        let owner = self.allocated_blocks.values().find(|block| (block.start..block.start + block.size).contains(&address));
+        let nearest = || {
+            self.allocated_blocks
+                .values()
+                .filter(|block| block.start + self.capacity(block) <= address)
+                .max_by_key(|block| block.start)
+        };
+        match owner {
+            Some(block) if address < block.start + self.capacity(block) => {
+                (Some(block.id), format!("0x{:04X} is located {} bytes inside the {}", address, address - block.start, region(block, false)))
+            }
+            _ => match owner.or_else(nearest) {
+                Some(block) => (
+                    Some(block.id),
+                    format!(
+                        "0x{:04X} is located {} bytes to the right of the {}",
+                        address,
+                        address - block.start - self.capacity(block),
+                        region(block, false)
+                    ),
+                ),
+                None => (None, format!("0x{:04X} is not inside or after any block", address)),
+            },
+        }
+    }
+
+    /// Returns the number of bytes of redzone to ask for after a new block
+    pub(crate) fn redzone(&self) -> usize {
+        self.shadow.as_ref().map_or(0, |shadow| shadow.config.redzone)
+    }
+
+    /// Returns how many bytes of data a block can hold, which leaves out its redzone
+    pub(crate) fn capacity(&self, block: &AllocatedBlock) -> usize {
+        match &self.shadow {
+            Some(shadow) => shadow.states[block.start..block.start + block.size]
+                .iter()
+                .position(|&state| state == ShadowState::Redzone)
+                .unwrap_or(block.size),
+            None => block.size,
+        }
+    }
+
+    /// Function to take a range from the allocation policy
+    /// When the policy has no room, the quarantine is given back first and the request retried
+    pub(crate) fn reserve(&mut self, size: usize) -> Option<FreeBlock> {
+        if let Some(block) = self.policy.allocate(size) {
+            return Some(block);
+        }
+        if self.shadow.as_ref().is_none_or(|shadow| shadow.quarantine.is_empty()) {
+            return None;
+        }
+        self.release_quarantine();
+        self.policy.allocate(size)
+    }
+
+    /// Function to poison a new block: its first `requested` bytes are uninitialized and the rest is redzone
+    pub(crate) fn mark_allocated(&mut self, start: usize, size: usize, requested: usize) {
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.forget_freed(start, size);
+            shadow.mark(start, requested, ShadowState::Uninitialized);
+            shadow.mark(start + requested, size - requested, ShadowState::Redzone);
+        }
+    }
+
+    /// Function to mark bytes of a block as written
+    pub(crate) fn mark_written(&mut self, start: usize, len: usize) {
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.mark(start, len, ShadowState::Addressable);
+        }
+    }
+
+    /// Function to mark bytes of a block as cleared, so they must be written before they are read
+    pub(crate) fn mark_cleared(&mut self, start: usize, len: usize) {
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.mark(start, len, ShadowState::Uninitialized);
+        }
+    }
+
+    /// Function to poison a block that was freed, remembering its ID for reports
+    pub(crate) fn mark_freed(&mut self, id: usize, start: usize, size: usize) {
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.mark(start, size, ShadowState::Freed);
+            shadow.freed.insert(start, (id, size));
+        }
+    }
+
+    /// Function to give a range back to the allocation policy, through the quarantine with shadow memory
+    /// The oldest ranges leave the quarantine once it holds more bytes than configured
+    pub(crate) fn release_range(&mut self, block: FreeBlock) {
+        let Some(shadow) = self.shadow.as_mut() else {
+            self.policy.release(block);
+            return;
+        };
+        shadow.quarantine.push_back(block);
+        while shadow.quarantined_bytes() > shadow.config.quarantine {
+            let oldest = shadow.quarantine.pop_front().unwrap();
+            self.policy.release(oldest);
+        }
+    }
+
+    /// Function to rebuild the shadow from the blocks, after the heap was replaced wholesale
+    /// The data of each block is addressable and the rest of it uninitialized, without redzones
+    pub(crate) fn rebuild_shadow(&mut self) {
+        let Some(config) = self.shadow.as_ref().map(|shadow| shadow.config) else {
+            return;
+        };
+        let mut shadow = Shadow::new(config, self.config.get_heap_size());
+        for block in self.allocated_blocks.values() {
+            shadow.mark(block.start, block.data_size, ShadowState::Addressable);
+            shadow.mark(block.start + block.data_size, block.size - block.data_size, ShadowState::Uninitialized);
+        }
+        self.shadow = Some(shadow);
+    }
+}
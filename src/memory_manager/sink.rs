@@ -0,0 +1,45 @@
+use std::io::Write;
+
+use super::outcome::CommandOutcome;
+
+/// OutputSink receives the outcome of every command executed by a memory manager.
+/// The manager has no sink by default, so it stays silent unless one is installed.
+pub trait OutputSink {
+    fn emit(&mut self, outcome: &CommandOutcome);
+}
+
+/// WriterSink renders outcomes as text lines on any writer, such as stdout or a file.
+pub struct WriterSink<W: Write> {
+    writer: W,
+}
+
+/// Implement WriterSink struct
+impl<W: Write> WriterSink<W> {
+    /// Creates a sink writing to the given writer
+    pub fn new(writer: W) -> Self {
+        WriterSink { writer }
+    }
+
+    /// Returns the writer, e.g. to inspect what was written to a buffer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Implement OutputSink for WriterSink
+/// Batches of zero commands print nothing, and write errors are ignored since a sink cannot report them
+impl<W: Write> OutputSink for WriterSink<W> {
+    fn emit(&mut self, outcome: &CommandOutcome) {
+        if matches!(outcome, CommandOutcome::Batch(outcomes) if outcomes.is_empty()) {
+            return;
+        }
+        let _ = writeln!(self.writer, "{}", outcome);
+    }
+}
+
+/// Implement OutputSink for closures, so any function taking an outcome can be used as a sink
+impl<F: FnMut(&CommandOutcome)> OutputSink for F {
+    fn emit(&mut self, outcome: &CommandOutcome) {
+        self(outcome)
+    }
+}
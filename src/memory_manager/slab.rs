@@ -0,0 +1,257 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::MemoryManager;
+use super::error::MemoryError;
+use super::free_block::FreeBlock;
+use super::handle::{Handle, Staleness};
+use super::trace::{TraceOp, TraceResult};
+
+/// Smallest page requested for a slab, so small objects are not spread over many tiny blocks
+pub const SLAB_PAGE_SIZE: usize = 256;
+
+/// Number of objects a slab should hold at least, when the heap allows it
+pub const SLAB_MIN_OBJECTS: usize = 8;
+
+/// Slab is one page of a slab cache, carved into objects of the cache's object size.
+#[derive(Clone, Debug)]
+pub(crate) struct Slab {
+    pub(crate) page_id: usize,
+    pub(crate) start: usize,
+    pub(crate) size: usize,
+    pub(crate) capacity: usize,
+    pub(crate) free_slots: BTreeSet<usize>,
+}
+
+/// Implement Slab struct
+impl Slab {
+    fn is_full(&self) -> bool {
+        self.free_slots.is_empty()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.free_slots.len() == self.capacity
+    }
+}
+
+/// SlabCache hands out objects of a single size, modelled on the kmem_cache of the Linux kernel.
+/// Pages are allocated from the heap as ordinary blocks and carved into objects. Objects are taken
+/// from partial slabs first, then from empty ones, and a new page is only allocated when every slab
+/// is full. At most one empty slab is kept, so the other empty pages go back to the heap.
+#[derive(Clone, Debug)]
+pub struct SlabCache {
+    pub(crate) name: String,
+    pub(crate) object_size: usize,
+    pub(crate) page_size: usize, // Size requested from the heap for each slab
+    pub(crate) slabs: Vec<Slab>,
+    pub(crate) objects: HashMap<usize, (usize, usize)>, // Object ID to page ID and slot
+}
+
+/// SlabStats describes the slabs and objects of a cache.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlabStats {
+    pub name: String,
+    pub object_size: usize,
+    pub full_slabs: usize,
+    pub partial_slabs: usize,
+    pub empty_slabs: usize,
+    pub objects_in_use: usize,
+    pub total_objects: usize,
+    pub page_bytes: usize, // Bytes of heap held by the slabs
+}
+
+/// Implement SlabCache struct
+impl SlabCache {
+    /// Returns the name of the cache
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the size of the objects handed out by the cache
+    pub fn get_object_size(&self) -> usize {
+        self.object_size
+    }
+
+    /// Returns the slab holding the object with the given ID, with the slot of the object
+    fn locate(&self, id: usize) -> Option<(usize, usize)> {
+        let &(page_id, slot) = self.objects.get(&id)?;
+        let index = self.slabs.iter().position(|slab| slab.page_id == page_id)?;
+        Some((index, slot))
+    }
+
+    /// Returns the slab an object should be taken from, preferring partial slabs over empty ones
+    fn slab_with_room(&self) -> Option<usize> {
+        let partial = self.slabs.iter().position(|slab| !slab.is_full() && !slab.is_empty());
+        partial.or_else(|| self.slabs.iter().position(|slab| !slab.is_full()))
+    }
+
+    /// Returns the statistics of the cache
+    pub fn stats(&self) -> SlabStats {
+        let count = |test: fn(&Slab) -> bool| self.slabs.iter().filter(|slab| test(slab)).count();
+        let empty_slabs = count(Slab::is_empty);
+        let full_slabs = count(Slab::is_full);
+        SlabStats {
+            name: self.name.clone(),
+            object_size: self.object_size,
+            full_slabs,
+            partial_slabs: self.slabs.len() - full_slabs - empty_slabs,
+            empty_slabs,
+            objects_in_use: self.objects.len(),
+            total_objects: self.slabs.iter().map(|slab| slab.capacity).sum(),
+            page_bytes: self.slabs.iter().map(|slab| slab.size).sum(),
+        }
+    }
+}
+
+/// Slab caches of the memory manager
+impl MemoryManager {
+    /// Function to create a slab cache handing out objects of `object_size` bytes
+    pub fn cache_create(&mut self, name: &str, object_size: usize) -> Result<(), MemoryError> {
+        let result = self.create_cache(name, object_size);
+        self.record(TraceOp::CacheCreate { name: name.to_string(), object_size }, TraceResult::of(&result));
+        result
+    }
+
+    /// Function to create a slab cache without recording it in the trace
+    fn create_cache(&mut self, name: &str, object_size: usize) -> Result<(), MemoryError> {
+        if object_size == 0 {
+            return Err(MemoryError::ZeroSize);
+        }
+        if self.caches.contains_key(name) {
+            return Err(MemoryError::CacheExists(name.to_string()));
+        }
+        let max_block_size = self.config.get_max_block_size().min(self.config.get_heap_size());
+        if object_size > max_block_size {
+            return Err(MemoryError::OutOfMemory { requested: object_size, largest_free: max_block_size });
+        }
+        let page_size = (object_size * SLAB_MIN_OBJECTS).max(SLAB_PAGE_SIZE).min(max_block_size);
+        let cache = SlabCache {
+            name: name.to_string(),
+            object_size,
+            page_size,
+            slabs: Vec::new(),
+            objects: HashMap::new(),
+        };
+        self.caches.insert(name.to_string(), cache);
+        Ok(())
+    }
+
+    /// Function to allocate an object from a slab cache
+    /// A new page is allocated from the heap when every slab of the cache is full
+    /// It returns the ID of the object, taken from the same counter as block IDs
+    pub fn cache_alloc(&mut self, name: &str) -> Result<usize, MemoryError> {
+        let result = self.alloc_object(name);
+        self.record(TraceOp::CacheAlloc { name: name.to_string() }, self.trace_object(name, &result));
+        result
+    }
+
+    /// Function to allocate an object from a slab cache without recording it in the trace
+    fn alloc_object(&mut self, name: &str) -> Result<usize, MemoryError> {
+        let cache = self.caches.get(name).ok_or_else(|| MemoryError::UnknownCache(name.to_string()))?;
+        let index = match cache.slab_with_room() {
+            Some(index) => index,
+            None => {
+                let (page_size, object_size) = (cache.page_size, cache.object_size);
+                let page_id = self.allocate(page_size)?;
+                let page = &self.allocated_blocks[&page_id];
+                let (start, size, capacity) = (page.start, page.size, self.capacity(page) / object_size);
+                self.set_data_size(page_id, capacity * object_size);
+
+                let cache = self.caches.get_mut(name).unwrap();
+                cache.slabs.push(Slab { page_id, start, size, capacity, free_slots: (0..capacity).collect() });
+                cache.slabs.len() - 1
+            }
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let cache = self.caches.get_mut(name).unwrap();
+        let slab = &mut cache.slabs[index];
+        let slot = slab.free_slots.pop_first().unwrap();
+        let address = slab.start + slot * cache.object_size;
+        cache.objects.insert(id, (slab.page_id, slot));
+        self.memory[address..address + cache.object_size].fill(0);
+        let object_size = cache.object_size;
+        self.mark_cleared(address, object_size);
+        Ok(id)
+    }
+
+    /// Function to give an object back to its slab cache
+    /// An emptied slab is kept for later allocations, unless the cache already has an empty slab
+    pub fn cache_free(&mut self, name: &str, id: usize) -> Result<(), MemoryError> {
+        let result = self.free_object(name, id);
+        self.record(TraceOp::CacheFree { name: name.to_string(), id }, TraceResult::of(&result));
+        result
+    }
+
+    /// Function to give an object back to its slab cache without recording it in the trace
+    fn free_object(&mut self, name: &str, id: usize) -> Result<(), MemoryError> {
+        let cache = self.caches.get(name).ok_or_else(|| MemoryError::UnknownCache(name.to_string()))?;
+        let Some((index, slot)) = cache.locate(id) else {
+            return Err(self.missing(id));
+        };
+        let cache = self.caches.get_mut(name).unwrap();
+        cache.objects.remove(&id);
+        cache.slabs[index].free_slots.insert(slot);
+        let (address, object_size) = (cache.slabs[index].start + slot * cache.object_size, cache.object_size);
+
+        let spare = cache.slabs.iter().filter(|slab| slab.is_empty()).count() > 1;
+        self.mark_freed(id, address, object_size);
+        if spare {
+            let page_id = self.caches.get_mut(name).unwrap().slabs.remove(index).page_id;
+            self.release_page(page_id);
+        }
+        self.retire(Handle::new(id, 0), Staleness::Freed);
+        Ok(())
+    }
+
+    /// Function to find the address of an object of a slab cache
+    pub fn cache_object_address(&self, name: &str, id: usize) -> Result<usize, MemoryError> {
+        let cache = self.caches.get(name).ok_or_else(|| MemoryError::UnknownCache(name.to_string()))?;
+        let (index, slot) = cache.locate(id).ok_or_else(|| self.missing(id))?;
+        Ok(cache.slabs[index].start + slot * cache.object_size)
+    }
+
+    /// Returns the statistics of every slab cache, ordered by name
+    pub fn cache_stats(&self) -> Vec<SlabStats> {
+        self.caches.values().map(SlabCache::stats).collect()
+    }
+
+    /// Returns the name of the slab cache that owns the page with the given block ID, if any
+    pub(crate) fn slab_owner(&self, id: usize) -> Option<&str> {
+        self.caches
+            .values()
+            .find(|cache| cache.slabs.iter().any(|slab| slab.page_id == id))
+            .map(|cache| cache.name.as_str())
+    }
+
+    /// Function to record how much of a page is taken by objects
+    fn set_data_size(&mut self, page_id: usize, data_size: usize) {
+        let page = self.allocated_blocks.get_mut(&page_id).unwrap();
+        self.usage.change_data(page.data_size, data_size);
+        page.data_size = data_size;
+    }
+
+    /// Function to give the page of a slab back to the heap
+    fn release_page(&mut self, page_id: usize) {
+        if let Some(page) = self.allocated_blocks.remove(&page_id) {
+            self.usage.remove(&page);
+            self.mark_freed(page_id, page.start, page.size);
+            self.release_range(FreeBlock::new(page.start, page.size));
+            self.retire(Handle::new(page_id, page.generation), Staleness::Freed);
+        }
+    }
+}
+
+/// Function to describe the statistics of a cache on one line of a dump
+pub(crate) fn stats_line(stats: &SlabStats) -> String {
+    format!(
+        "{}: object size {} bytes, {}/{} objects in use, slabs {} full / {} partial / {} empty",
+        stats.name,
+        stats.object_size,
+        stats.objects_in_use,
+        stats.total_objects,
+        stats.full_slabs,
+        stats.partial_slabs,
+        stats.empty_slabs
+    )
+}
@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+
+use super::MemoryManager;
+use super::allocated_block::AllocatedBlock;
+use super::arena::{Arena, ArenaChunk};
+use super::config::MemoryConfig;
+use super::error::MemoryError;
+use super::free_block::FreeBlock;
+use super::policy::create_policy;
+use super::slab::{Slab, SlabCache};
+use super::stats::Usage;
+
+/// Bytes every snapshot starts with
+const MAGIC: &[u8; 8] = b"CMMSNAP\0";
+
+/// Version of the snapshot layout written by `to_snapshot`
+/// Version 2 added the slab caches and version 3 the arena, and older snapshots are still accepted
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// Function to compute the CRC-32 (IEEE) checksum of some bytes
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Function to check that a block read from a snapshot lies inside the heap
+/// Sizes come from an untrusted file, so the end of the block is computed without overflowing
+fn inside_heap(start: usize, size: usize, heap_size: usize) -> bool {
+    size > 0 && start.checked_add(size).is_some_and(|end| end <= heap_size)
+}
+
+/// SnapshotWriter appends little-endian fields to a snapshot buffer.
+struct SnapshotWriter {
+    bytes: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: usize) {
+        self.bytes.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+/// SnapshotReader reads the fields written by SnapshotWriter, failing on truncated input.
+struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MemoryError> {
+        let end = self.position.checked_add(len).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| MemoryError::InvalidSnapshot("unexpected end of data".to_string()))?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, MemoryError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<usize, MemoryError> {
+        let value = u64::from_le_bytes(self.take(8)?.try_into().unwrap());
+        usize::try_from(value).map_err(|_| MemoryError::InvalidSnapshot(format!("value {} is too large", value)))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], MemoryError> {
+        let len = self.u64()?;
+        self.take(len)
+    }
+}
+
+/// Snapshots of the memory manager state
+/// A snapshot holds the heap configuration, the policy name, next_id, the memory array, the free
+/// blocks, the allocated blocks, the slab caches and the arena, followed by a CRC-32 of everything
+/// before it.
+impl MemoryManager {
+    /// Function to write the state of the manager as a versioned binary snapshot
+    /// Ranges held in the quarantine of the shadow memory are in neither list, so `flush_quarantine`
+    /// should be called first for the snapshot to be restorable
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter { bytes: MAGIC.to_vec() };
+        writer.u32(SNAPSHOT_VERSION);
+        writer.u64(self.config.get_heap_size());
+        writer.u64(self.config.get_min_block_size());
+        writer.u64(self.config.get_max_order());
+        writer.bytes(self.policy.name().as_bytes());
+        writer.u64(self.next_id);
+        writer.bytes(&self.memory);
+
+        let mut free_blocks = self.policy.free_blocks();
+        free_blocks.sort_by_key(|block| block.start);
+        writer.u64(free_blocks.len());
+        for block in &free_blocks {
+            writer.u64(block.start);
+            writer.u64(block.size);
+        }
+
+        let mut allocated: Vec<&AllocatedBlock> = self.allocated_blocks.values().collect();
+        allocated.sort_by_key(|block| block.id);
+        writer.u64(allocated.len());
+        for block in allocated {
+            writer.u64(block.id);
+            writer.u64(block.start);
+            writer.u64(block.size);
+            writer.u64(block.data_size);
+        }
+
+        writer.u64(self.caches.len());
+        for cache in self.caches.values() {
+            writer.bytes(cache.name.as_bytes());
+            writer.u64(cache.object_size);
+            writer.u64(cache.page_size);
+            writer.u64(cache.slabs.len());
+            for slab in &cache.slabs {
+                let mut objects: Vec<(usize, usize)> = cache
+                    .objects
+                    .iter()
+                    .filter(|(_, (page_id, _))| *page_id == slab.page_id)
+                    .map(|(&id, &(_, slot))| (id, slot))
+                    .collect();
+                objects.sort();
+                writer.u64(slab.page_id);
+                writer.u64(objects.len());
+                for (id, slot) in objects {
+                    writer.u64(id);
+                    writer.u64(slot);
+                }
+            }
+        }
+
+        writer.u64(self.arena.chunks.len());
+        for chunk in &self.arena.chunks {
+            writer.u64(chunk.start);
+            writer.u64(chunk.size);
+        }
+        writer.u64(self.arena.blocks.len());
+        for &id in &self.arena.blocks {
+            writer.u64(id);
+        }
+
+        let checksum = crc32(&writer.bytes);
+        writer.u32(checksum);
+        writer.bytes
+    }
+
+    /// Function to replace the state of the manager with the one stored in a snapshot
+    /// The snapshot is fully validated first, so the manager is left untouched if it is rejected
+    pub fn restore_snapshot(&mut self, bytes: &[u8]) -> Result<(), MemoryError> {
+        let invalid = |reason: String| MemoryError::InvalidSnapshot(reason);
+        if self.trace.is_some() {
+            // A replay starts from an empty heap and has no way to load the same snapshot
+            return Err(MemoryError::InvalidCommand("cannot load a snapshot while a trace is recorded".to_string()));
+        }
+
+        if bytes.len() < MAGIC.len() + 4 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a memory manager snapshot".to_string()));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 4);
+        if crc32(body) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err(invalid("checksum mismatch".to_string()));
+        }
+
+        let mut reader = SnapshotReader { bytes: body, position: MAGIC.len() };
+        let version = reader.u32()?;
+        if version == 0 || version > SNAPSHOT_VERSION {
+            return Err(invalid(format!("unsupported version {}", version)));
+        }
+
+        let config = MemoryConfig::new()
+            .with_heap_size(reader.u64()?)
+            .with_min_block_size(reader.u64()?)
+            .with_max_order(reader.u64()?);
+        config.validate()?;
+        let policy_name = String::from_utf8_lossy(reader.bytes()?).into_owned();
+        let mut policy = create_policy(&policy_name, &config)?;
+        let next_id = reader.u64()?;
+        let memory = reader.bytes()?.to_vec();
+        if memory.len() != config.get_heap_size() {
+            return Err(invalid(format!("memory holds {} bytes instead of {}", memory.len(), config.get_heap_size())));
+        }
+
+        // Every block must lie inside the heap, and together they must cover it without overlapping
+        let heap_size = config.get_heap_size();
+        let mut ranges = Vec::new();
+        let mut free_blocks = Vec::new();
+        for _ in 0..reader.u64()? {
+            let block = FreeBlock::new(reader.u64()?, reader.u64()?);
+            if !inside_heap(block.start, block.size, heap_size) {
+                return Err(invalid(format!("{} does not fit in the heap", block)));
+            }
+            ranges.push((block.start, block.size));
+            free_blocks.push(block);
+        }
+        let mut allocated_blocks = HashMap::new();
+        for _ in 0..reader.u64()? {
+            let (id, start, size, data_size) = (reader.u64()?, reader.u64()?, reader.u64()?, reader.u64()?);
+            if id >= next_id || data_size > size || allocated_blocks.contains_key(&id) || !inside_heap(start, size, heap_size) {
+                return Err(invalid(format!("allocated block {} is inconsistent", id)));
+            }
+            allocated_blocks.insert(id, AllocatedBlock::new(start, size, id, data_size));
+        }
+        let caches = if version >= 2 { read_caches(&mut reader, &allocated_blocks, next_id)? } else { BTreeMap::new() };
+        let arena = if version >= 3 { read_arena(&mut reader, &allocated_blocks, heap_size)? } else { Arena::default() };
+        if reader.position != body.len() {
+            return Err(invalid("trailing data after the blocks".to_string()));
+        }
+
+        // Arena blocks lie inside the arena chunks, so the chunks take their place in the heap
+        ranges.extend(arena.chunks.iter().map(|chunk| (chunk.start, chunk.size)));
+        ranges.extend(
+            allocated_blocks
+                .values()
+                .filter(|block| !arena.blocks.contains(&block.id))
+                .map(|block| (block.start, block.size)),
+        );
+        ranges.sort();
+        let mut expected_start = 0;
+        for (start, size) in ranges {
+            let end = start.checked_add(size).filter(|_| start == expected_start && size > 0);
+            expected_start = end.ok_or_else(|| invalid(format!("blocks do not tile the heap at address 0x{:04X}", expected_start)))?;
+        }
+        if expected_start != heap_size {
+            return Err(invalid("blocks do not cover the whole heap".to_string()));
+        }
+        policy.restore_free_blocks(&free_blocks).map_err(invalid)?;
+
+        // The blocks taken from the policy must follow its rules too, such as buddy alignment
+        let mut policy_blocks: Vec<FreeBlock> = allocated_blocks
+            .values()
+            .filter(|block| !arena.blocks.contains(&block.id))
+            .map(|block| FreeBlock::new(block.start, block.size))
+            .collect();
+        policy_blocks.extend(arena.chunks.iter().map(|chunk| FreeBlock::new(chunk.start, chunk.size)));
+        if let Some(violation) = policy.verify(&policy_blocks).into_iter().next() {
+            return Err(invalid(violation));
+        }
+
+        self.config = config;
+        self.memory = memory;
+        self.policy = policy;
+        self.allocated_blocks = allocated_blocks;
+        self.next_id = next_id;
+        self.caches = caches;
+        self.arena = arena;
+        self.usage = Usage::recount(&self.allocated_blocks);
+        self.retired.clear();
+        self.retired_order.clear();
+        self.rebuild_shadow();
+        Ok(())
+    }
+
+    /// Function to write a snapshot of the manager to a file
+    pub fn save_snapshot(&self, path: &str) -> Result<(), MemoryError> {
+        fs::write(path, self.to_snapshot())?;
+        Ok(())
+    }
+
+    /// Function to load a snapshot from a file, replacing the state of the manager
+    pub fn load_snapshot(&mut self, path: &str) -> Result<(), MemoryError> {
+        let bytes = fs::read(path)?;
+        self.restore_snapshot(&bytes)
+    }
+}
+
+/// Function to read the slab caches of a snapshot, checking them against the allocated blocks
+/// Every slab must be an allocated page, and objects must have unique IDs and slots inside their slab
+fn read_caches(
+    reader: &mut SnapshotReader,
+    allocated_blocks: &HashMap<usize, AllocatedBlock>,
+    next_id: usize,
+) -> Result<BTreeMap<String, SlabCache>, MemoryError> {
+    let invalid = |reason: String| MemoryError::InvalidSnapshot(reason);
+    let mut caches = BTreeMap::new();
+    let mut object_ids = BTreeSet::new();
+    let mut page_ids = BTreeSet::new();
+
+    for _ in 0..reader.u64()? {
+        let name = String::from_utf8_lossy(reader.bytes()?).into_owned();
+        let (object_size, page_size) = (reader.u64()?, reader.u64()?);
+        if object_size == 0 || caches.contains_key(&name) {
+            return Err(invalid(format!("slab cache '{}' is inconsistent", name)));
+        }
+        let mut cache = SlabCache { name: name.clone(), object_size, page_size, slabs: Vec::new(), objects: HashMap::new() };
+
+        for _ in 0..reader.u64()? {
+            let page_id = reader.u64()?;
+            let page = allocated_blocks.get(&page_id).filter(|_| page_ids.insert(page_id));
+            let page = page.ok_or_else(|| invalid(format!("slab page {} of cache '{}' is not allocated", page_id, name)))?;
+            let capacity = page.size / object_size;
+            let mut slab = Slab { page_id, start: page.start, size: page.size, capacity, free_slots: (0..capacity).collect() };
+
+            for _ in 0..reader.u64()? {
+                let (id, slot) = (reader.u64()?, reader.u64()?);
+                let fresh_id = id < next_id && !allocated_blocks.contains_key(&id) && object_ids.insert(id);
+                if !fresh_id || !slab.free_slots.remove(&slot) {
+                    return Err(invalid(format!("object {} of cache '{}' is inconsistent", id, name)));
+                }
+                cache.objects.insert(id, (page_id, slot));
+            }
+            cache.slabs.push(slab);
+        }
+        caches.insert(name, cache);
+    }
+    Ok(caches)
+}
+
+/// Function to read the arena of a snapshot, checking that its blocks lie inside its chunks
+/// without overlapping
+fn read_arena(
+    reader: &mut SnapshotReader,
+    allocated_blocks: &HashMap<usize, AllocatedBlock>,
+    heap_size: usize,
+) -> Result<Arena, MemoryError> {
+    let invalid = |reason: String| MemoryError::InvalidSnapshot(reason);
+    let mut arena = Arena::default();
+    for _ in 0..reader.u64()? {
+        let chunk = ArenaChunk { start: reader.u64()?, size: reader.u64()?, used: 0 };
+        if !inside_heap(chunk.start, chunk.size, heap_size) {
+            return Err(invalid(format!("arena chunk at 0x{:04X} does not fit in the heap", chunk.start)));
+        }
+        arena.chunks.push(chunk);
+    }
+
+    let mut ranges = Vec::new();
+    for _ in 0..reader.u64()? {
+        let id = reader.u64()?;
+        let block = allocated_blocks.get(&id).filter(|_| !arena.blocks.contains(&id));
+        let block = block.ok_or_else(|| invalid(format!("arena block {} is not allocated", id)))?;
+        let inside = |chunk: &ArenaChunk| chunk.start <= block.start && block.start + block.size <= chunk.start + chunk.size;
+        if !arena.chunks.iter().any(inside) {
+            return Err(invalid(format!("arena block {} is outside the arena chunks", id)));
+        }
+        ranges.push((block.start, block.size));
+        arena.blocks.push(id);
+    }
+    ranges.sort();
+    if ranges.windows(2).any(|pair| pair[0].0 + pair[0].1 > pair[1].0) {
+        return Err(invalid("arena blocks overlap".to_string()));
+    }
+    arena.update_usage(allocated_blocks);
+    Ok(arena)
+}
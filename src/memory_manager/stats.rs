@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::json::JsonValue;
+
+use super::MemoryManager;
+use super::allocated_block::AllocatedBlock;
+
+/// Usage keeps running totals of the allocated blocks, updated on every insert, delete and update
+/// so the statistics do not need to walk the heap, together with the highest values they reached.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub blocks: usize,
+    pub allocated_bytes: usize,
+    pub data_bytes: usize,
+    pub peak_blocks: usize,
+    pub peak_allocated_bytes: usize,
+    pub peak_data_bytes: usize,
+    pub high_water_mark: usize, // End of the highest block ever allocated
+}
+
+/// Implement Usage struct
+impl Usage {
+    /// Function to count a newly allocated block
+    /// The peak of data bytes is left alone, since a new block counts its requested size as data until
+    /// the actual data is stored; callers that keep the requested size as data call `update_peaks`
+    pub(crate) fn add(&mut self, block: &AllocatedBlock) {
+        self.blocks += 1;
+        self.allocated_bytes += block.size;
+        self.data_bytes += block.data_size;
+        self.high_water_mark = self.high_water_mark.max(block.start + block.size);
+        self.peak_blocks = self.peak_blocks.max(self.blocks);
+        self.peak_allocated_bytes = self.peak_allocated_bytes.max(self.allocated_bytes);
+    }
+
+    /// Function to stop counting a freed block
+    pub(crate) fn remove(&mut self, block: &AllocatedBlock) {
+        self.blocks -= 1;
+        self.allocated_bytes -= block.size;
+        self.data_bytes -= block.data_size;
+    }
+
+    /// Function to count a change of the data stored in a block
+    pub(crate) fn change_data(&mut self, old_data_size: usize, new_data_size: usize) {
+        self.data_bytes = self.data_bytes - old_data_size + new_data_size;
+        self.update_peaks();
+    }
+
+    /// Function to raise the peaks to the current totals
+    pub(crate) fn update_peaks(&mut self) {
+        self.peak_blocks = self.peak_blocks.max(self.blocks);
+        self.peak_allocated_bytes = self.peak_allocated_bytes.max(self.allocated_bytes);
+        self.peak_data_bytes = self.peak_data_bytes.max(self.data_bytes);
+    }
+
+    /// Function to count the blocks of a restored heap, whose earlier peaks are not known
+    pub(crate) fn recount(allocated_blocks: &HashMap<usize, AllocatedBlock>) -> Usage {
+        let mut usage = Usage::default();
+        for block in allocated_blocks.values() {
+            usage.add(block);
+        }
+        usage.update_peaks();
+        usage
+    }
+}
+
+/// HeapStats describes how well the heap is used at one point in time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeapStats {
+    pub heap_size: usize,
+    pub usage: Usage,
+    pub internal_fragmentation: usize, // Bytes of allocated blocks not holding data
+    pub free_bytes: usize,
+    pub free_blocks: usize,
+    pub largest_free_block: usize,
+    pub external_fragmentation: f64, // 1 - largest free block / free bytes
+    pub utilization: f64,            // Allocated bytes / heap size
+    pub free_blocks_by_order: BTreeMap<usize, usize>, // Number of free blocks of each order
+}
+
+/// Implement HeapStats struct
+impl HeapStats {
+    /// Function to describe the statistics as JSON
+    pub fn to_json_value(&self) -> JsonValue {
+        JsonValue::object([
+            ("heap_size", self.heap_size.into()),
+            ("allocated_blocks", self.usage.blocks.into()),
+            ("allocated_bytes", self.usage.allocated_bytes.into()),
+            ("data_bytes", self.usage.data_bytes.into()),
+            ("internal_fragmentation", self.internal_fragmentation.into()),
+            ("free_bytes", self.free_bytes.into()),
+            ("free_blocks", self.free_blocks.into()),
+            ("largest_free_block", self.largest_free_block.into()),
+            ("external_fragmentation", self.external_fragmentation.into()),
+            ("utilization", self.utilization.into()),
+            (
+                "free_blocks_by_order",
+                JsonValue::Array(
+                    self.free_blocks_by_order
+                        .iter()
+                        .map(|(&order, &count)| JsonValue::object([("order", order.into()), ("count", count.into())]))
+                        .collect(),
+                ),
+            ),
+            ("peak_blocks", self.usage.peak_blocks.into()),
+            ("peak_allocated_bytes", self.usage.peak_allocated_bytes.into()),
+            ("peak_data_bytes", self.usage.peak_data_bytes.into()),
+            ("high_water_mark", self.usage.high_water_mark.into()),
+        ])
+    }
+}
+
+/// Implement Display for HeapStats
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Heap Stats:")?;
+        writeln!(f, "  Heap size: {} bytes", self.heap_size)?;
+        writeln!(
+            f,
+            "  Allocated: {} block(s), {} bytes, {} bytes of data",
+            self.usage.blocks, self.usage.allocated_bytes, self.usage.data_bytes
+        )?;
+        writeln!(f, "  Utilization: {:.1}%", self.utilization * 100.0)?;
+        writeln!(f, "  Internal fragmentation: {} bytes", self.internal_fragmentation)?;
+        writeln!(
+            f,
+            "  Free: {} block(s), {} bytes, largest {} bytes",
+            self.free_blocks, self.free_bytes, self.largest_free_block
+        )?;
+        writeln!(f, "  External fragmentation: {:.1}%", self.external_fragmentation * 100.0)?;
+        for (order, count) in &self.free_blocks_by_order {
+            writeln!(f, "  Free blocks of order {}: {}", order, count)?;
+        }
+        writeln!(
+            f,
+            "  Peak: {} block(s), {} bytes allocated, {} bytes of data",
+            self.usage.peak_blocks, self.usage.peak_allocated_bytes, self.usage.peak_data_bytes
+        )?;
+        write!(f, "  High-water mark: 0x{:04X}", self.usage.high_water_mark)
+    }
+}
+
+/// Statistics of the memory manager
+impl MemoryManager {
+    /// Function to measure the fragmentation and utilization of the heap
+    /// Totals and peaks come from the running usage, so only the free blocks are walked
+    /// The order of a free block is the log2 of its size in minimum blocks, rounded down
+    pub fn stats(&self) -> HeapStats {
+        let free_blocks = self.policy.free_blocks();
+        let free_bytes: usize = free_blocks.iter().map(|block| block.size).sum();
+        let largest_free_block = free_blocks.iter().map(|block| block.size).max().unwrap_or(0);
+        let mut free_blocks_by_order = BTreeMap::new();
+        for block in &free_blocks {
+            let order = (block.size / self.config.get_min_block_size()).max(1).ilog2() as usize;
+            *free_blocks_by_order.entry(order).or_insert(0) += 1;
+        }
+
+        let heap_size = self.config.get_heap_size();
+        HeapStats {
+            heap_size,
+            usage: self.usage,
+            internal_fragmentation: self.usage.allocated_bytes - self.usage.data_bytes,
+            free_bytes,
+            free_blocks: free_blocks.len(),
+            largest_free_block,
+            external_fragmentation: if free_bytes == 0 { 0.0 } else { 1.0 - largest_free_block as f64 / free_bytes as f64 },
+            utilization: self.usage.allocated_bytes as f64 / heap_size as f64,
+            free_blocks_by_order,
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::config::MemoryConfig;
+use super::free_block::FreeBlock;
+use super::policy::{AllocationPolicy, PolicyCounters, verify_coalesced_blocks};
+
+/// Number of bits of a size used for the second level, giving 16 size classes per power of two
+const SL_BITS: u32 = 4;
+
+/// Number of second-level size classes per first-level class
+const SL_COUNT: usize = 1 << SL_BITS;
+
+/// TlsfPolicy is a two-level segregated fit allocation policy, as used in real-time systems.
+/// Free blocks are kept in size classes: the first level splits sizes by power of two, and the
+/// second level splits each power of two in 16 linear steps. A bitmap per level records which
+/// classes hold free blocks, so finding a block takes a couple of bit scans whatever the heap holds.
+///
+/// Sizes are only rounded up to a multiple of the minimum block size. A block taken from a class
+/// is split when it is larger than needed, and freed blocks are merged with the free blocks directly
+/// before and after them. Since the memory buffer only holds user data, the boundary tags of a
+/// classic TLSF heap are replaced by a map of free blocks by start address.
+#[derive(Clone, Debug)]
+pub struct TlsfPolicy {
+    min_block_size: usize,
+    heap_size: usize,
+    fl_bitmap: u64,
+    sl_bitmaps: Vec<u32>,
+    classes: Vec<[BTreeSet<usize>; SL_COUNT]>, // Start addresses of the free blocks of each size class
+    free: BTreeMap<usize, usize>,              // Size of every free block by start address
+    counters: PolicyCounters,
+}
+
+/// Function to find the first- and second-level class of a block size
+/// Sizes below SL_COUNT get one class each in the first level, larger sizes are split by their
+/// leading bits.
+fn mapping(size: usize) -> (usize, usize) {
+    if size < SL_COUNT {
+        (0, size)
+    } else {
+        let fl = size.ilog2();
+        ((fl - SL_BITS + 1) as usize, (size >> (fl - SL_BITS)) - SL_COUNT)
+    }
+}
+
+/// Function to find the first class whose blocks are all at least `size` bytes long
+fn mapping_search(size: usize) -> (usize, usize) {
+    if size < SL_COUNT {
+        return (0, size);
+    }
+    let rounded = size.saturating_add((1 << (size.ilog2() - SL_BITS)) - 1);
+    mapping(rounded)
+}
+
+/// Implement TlsfPolicy struct
+impl TlsfPolicy {
+    /// Creates a new TLSF policy managing a single free block covering the heap of a validated configuration.
+    pub fn with_config(config: &MemoryConfig) -> Self {
+        let heap_size = config.get_heap_size();
+        let levels = mapping(heap_size).0 + 1;
+        let mut policy = TlsfPolicy {
+            min_block_size: config.get_min_block_size(),
+            heap_size,
+            fl_bitmap: 0,
+            sl_bitmaps: vec![0; levels],
+            classes: vec![Default::default(); levels],
+            free: BTreeMap::new(),
+            counters: PolicyCounters::default(),
+        };
+        policy.insert_block(0, heap_size);
+        policy
+    }
+
+    /// Adds a free block to its size class
+    fn insert_block(&mut self, start: usize, size: usize) {
+        let (fl, sl) = mapping(size);
+        self.classes[fl][sl].insert(start);
+        self.sl_bitmaps[fl] |= 1 << sl;
+        self.fl_bitmap |= 1 << fl;
+        self.free.insert(start, size);
+    }
+
+    /// Removes a free block from its size class
+    fn remove_block(&mut self, start: usize, size: usize) {
+        let (fl, sl) = mapping(size);
+        self.classes[fl][sl].remove(&start);
+        if self.classes[fl][sl].is_empty() {
+            self.sl_bitmaps[fl] &= !(1 << sl);
+            if self.sl_bitmaps[fl] == 0 {
+                self.fl_bitmap &= !(1 << fl);
+            }
+        }
+        self.free.remove(&start);
+    }
+
+    /// Returns the first non-empty size class at or above the given one, using the bitmaps
+    fn find_suitable(&mut self, fl: usize, sl: usize) -> Option<(usize, usize)> {
+        self.counters.search_steps += 1;
+        let sl_map = self.sl_bitmaps.get(fl).copied().unwrap_or(0) & (u32::MAX << sl);
+        if sl_map != 0 {
+            return Some((fl, sl_map.trailing_zeros() as usize));
+        }
+
+        self.counters.search_steps += 1;
+        let fl_map = self.fl_bitmap & u64::MAX.checked_shl(fl as u32 + 1).unwrap_or(0);
+        if fl_map == 0 {
+            return None;
+        }
+        let fl = fl_map.trailing_zeros() as usize;
+        Some((fl, self.sl_bitmaps[fl].trailing_zeros() as usize))
+    }
+
+    /// Adds a free block, merging it with the free blocks that touch it
+    fn insert_free(&mut self, mut start: usize, mut size: usize) {
+        if let Some((&before, &before_size)) = self.free.range(..start).next_back()
+            && before + before_size == start
+        {
+            self.remove_block(before, before_size);
+            start = before;
+            size += before_size;
+            self.counters.merges += 1;
+        }
+        if let Some(&after_size) = self.free.get(&(start + size)) {
+            self.remove_block(start + size, after_size);
+            size += after_size;
+            self.counters.merges += 1;
+        }
+        self.insert_block(start, size);
+    }
+}
+
+/// Implement AllocationPolicy for TlsfPolicy
+impl AllocationPolicy for TlsfPolicy {
+    fn name(&self) -> &'static str {
+        "tlsf"
+    }
+
+    fn block_size(&self, requested: usize) -> usize {
+        requested.max(1).next_multiple_of(self.min_block_size)
+    }
+
+    /// Takes the lowest block of the first size class that is large enough, and frees the unused tail
+    fn allocate(&mut self, requested: usize) -> Option<FreeBlock> {
+        let size = self.block_size(requested);
+        let (fl, sl) = mapping_search(size);
+        let found = if size > self.heap_size { None } else { self.find_suitable(fl, sl) };
+        let Some((fl, sl)) = found else {
+            self.counters.failed_allocations += 1;
+            return None;
+        };
+
+        let start = *self.classes[fl][sl].first()?;
+        let free_size = self.free[&start];
+        self.remove_block(start, free_size);
+        if free_size > size {
+            self.insert_block(start + size, free_size - size);
+            self.counters.splits += 1;
+        }
+        self.counters.allocations += 1;
+
+        let mut block = FreeBlock::new(start, size);
+        block.is_free = false;
+        Some(block)
+    }
+
+    fn release(&mut self, block: FreeBlock) {
+        self.counters.releases += 1;
+        self.insert_free(block.start, block.size);
+    }
+
+    /// Blocks are merged as soon as they are released, so there is never anything left to merge
+    fn merge_free_blocks(&mut self) {}
+
+    fn free_blocks(&self) -> Vec<FreeBlock> {
+        self.free.iter().map(|(&start, &size)| FreeBlock::new(start, size)).collect()
+    }
+
+    /// Rebuilds the size classes, merging free blocks that touch
+    fn restore_free_blocks(&mut self, free_blocks: &[FreeBlock]) -> Result<(), String> {
+        let mut restored = TlsfPolicy {
+            fl_bitmap: 0,
+            sl_bitmaps: vec![0; self.sl_bitmaps.len()],
+            classes: vec![Default::default(); self.classes.len()],
+            free: BTreeMap::new(),
+            ..self.clone()
+        };
+        for block in free_blocks {
+            if block.size == 0 || block.start.checked_add(block.size).is_none_or(|end| end > self.heap_size) {
+                return Err(format!("{} does not fit in the heap", block));
+            }
+            restored.insert_free(block.start, block.size);
+        }
+        restored.counters = self.counters;
+        *self = restored;
+        Ok(())
+    }
+
+    fn counters(&self) -> PolicyCounters {
+        self.counters
+    }
+
+    /// Also checks that every free block is in its size class and that the bitmaps match the classes
+    fn verify(&self, allocated: &[FreeBlock]) -> Vec<String> {
+        let mut violations = verify_coalesced_blocks(&self.free_blocks(), allocated, self.min_block_size);
+        for (&start, &size) in &self.free {
+            let (fl, sl) = mapping(size);
+            if !self.classes.get(fl).is_some_and(|classes| classes[sl].contains(&start)) {
+                violations.push(format!("free block at 0x{:04X} ({} bytes) is missing from its size class", start, size));
+            }
+        }
+        let classified: usize = self.classes.iter().flatten().map(BTreeSet::len).sum();
+        if classified != self.free.len() {
+            violations.push(format!("size classes hold {} block(s) but {} are free", classified, self.free.len()));
+        }
+        for (fl, classes) in self.classes.iter().enumerate() {
+            for (sl, class) in classes.iter().enumerate() {
+                if class.is_empty() == (self.sl_bitmaps[fl] & (1 << sl) != 0) {
+                    violations.push(format!("second-level bitmap of class ({}, {}) does not match its blocks", fl, sl));
+                }
+            }
+            if (self.sl_bitmaps[fl] == 0) == (self.fl_bitmap & (1 << fl) != 0) {
+                violations.push(format!("first-level bitmap of class {} does not match its second level", fl));
+            }
+        }
+        violations
+    }
+}
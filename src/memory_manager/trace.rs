@@ -0,0 +1,313 @@
+use std::fmt;
+
+use crate::parse_trace_number;
+
+use super::MemoryManager;
+use super::config::MemoryConfig;
+use super::error::MemoryError;
+use super::shadow::ShadowConfig;
+
+/// TraceOp is a mutation of the memory manager recorded in a trace.
+/// Only the length of stored data is kept, since the data itself never changes where blocks go.
+/// For the same reason raw writes with POKE and WRITE are not recorded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceOp {
+    Insert { size: usize },
+    Set { id: usize, len: usize },
+    Update { id: usize, len: usize },
+    UpdateStable { id: usize, len: usize },
+    Delete { id: usize },
+    Merge,
+    ArenaInsert { size: usize },
+    ArenaReset { mark: usize },
+    CacheCreate { name: String, object_size: usize },
+    CacheAlloc { name: String },
+    CacheFree { name: String, id: usize },
+    Policy { name: String },
+    FlushQuarantine,
+}
+
+/// TraceResult is what a recorded mutation produced.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceResult {
+    /// The block holding the data after an insert or update.
+    Block { id: usize, start: usize, size: usize },
+    /// The mutation succeeded without placing a block.
+    Done,
+    /// The mutation failed with the given message.
+    Failed(String),
+}
+
+/// TraceEvent is one numbered mutation of a trace, with its result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub seq: usize,
+    pub op: TraceOp,
+    pub result: TraceResult,
+}
+
+/// Trace is the list of mutations made on a memory manager, from the moment recording started.
+/// Its Display implementation gives the trace file format, one event per line after a header
+/// naming the policy, heap configuration and shadow memory settings, e.g.
+/// `12 insert 100 -> id=4 addr=0x0080 size=128`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub policy: String,
+    pub config: MemoryConfig,
+    pub shadow: Option<ShadowConfig>, // Redzones and quarantine change where blocks go, so they are replayed too
+    pub events: Vec<TraceEvent>,
+}
+
+/// Implement TraceResult enum
+impl TraceResult {
+    /// Function to describe the result of a mutation that does not place a block
+    pub(crate) fn of<T>(result: &Result<T, MemoryError>) -> TraceResult {
+        match result {
+            Ok(_) => TraceResult::Done,
+            Err(e) => TraceResult::Failed(e.to_string()),
+        }
+    }
+}
+
+/// Implement Display for TraceOp
+impl fmt::Display for TraceOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceOp::Insert { size } => write!(f, "insert {}", size),
+            TraceOp::Set { id, len } => write!(f, "set {} {}", id, len),
+            TraceOp::Update { id, len } => write!(f, "update {} {}", id, len),
+            TraceOp::UpdateStable { id, len } => write!(f, "update-stable {} {}", id, len),
+            TraceOp::Delete { id } => write!(f, "delete {}", id),
+            TraceOp::Merge => write!(f, "merge"),
+            TraceOp::ArenaInsert { size } => write!(f, "arena-insert {}", size),
+            TraceOp::ArenaReset { mark } => write!(f, "arena-reset {}", mark),
+            TraceOp::CacheCreate { name, object_size } => write!(f, "cache-create {} {}", name, object_size),
+            TraceOp::CacheAlloc { name } => write!(f, "cache-alloc {}", name),
+            TraceOp::CacheFree { name, id } => write!(f, "cache-free {} {}", name, id),
+            TraceOp::Policy { name } => write!(f, "policy {}", name),
+            TraceOp::FlushQuarantine => write!(f, "flush-quarantine"),
+        }
+    }
+}
+
+/// Implement Display for TraceResult
+impl fmt::Display for TraceResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceResult::Block { id, start, size } => write!(f, "id={} addr=0x{:04X} size={}", id, start, size),
+            TraceResult::Done => write!(f, "ok"),
+            TraceResult::Failed(message) => write!(f, "error: {}", message),
+        }
+    }
+}
+
+/// Implement Display for Trace
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "# trace policy={} heap_size={} min_block_size={} max_order={}",
+            self.policy,
+            self.config.get_heap_size(),
+            self.config.get_min_block_size(),
+            self.config.get_max_order()
+        )?;
+        if let Some(shadow) = self.shadow {
+            write!(f, " redzone={} quarantine={}", shadow.get_redzone(), shadow.get_quarantine())?;
+        }
+        writeln!(f)?;
+        for event in &self.events {
+            writeln!(f, "{} {} -> {}", event.seq, event.op, event.result)?;
+        }
+        Ok(())
+    }
+}
+
+/// Function to parse the result of a trace line
+fn parse_result(text: &str, line: usize) -> Result<TraceResult, String> {
+    if text == "ok" {
+        return Ok(TraceResult::Done);
+    }
+    if let Some(message) = text.strip_prefix("error: ") {
+        return Ok(TraceResult::Failed(message.to_string()));
+    }
+    let mut fields = text.split_whitespace().map(|field| field.split_once('='));
+    match (fields.next(), fields.next(), fields.next(), fields.next()) {
+        (Some(Some(("id", id))), Some(Some(("addr", addr))), Some(Some(("size", size))), None) => {
+            let start = addr.strip_prefix("0x").and_then(|hex| usize::from_str_radix(hex, 16).ok());
+            Ok(TraceResult::Block {
+                id: parse_trace_number(Some(id), line)?,
+                start: start.ok_or_else(|| format!("line {}: invalid address '{}'", line, addr))?,
+                size: parse_trace_number(Some(size), line)?,
+            })
+        }
+        _ => Err(format!("line {}: invalid result '{}'", line, text)),
+    }
+}
+
+/// Implement Trace struct
+impl Trace {
+    /// Creates an empty trace for a manager with the given policy and configuration
+    pub fn new(policy: &str, config: MemoryConfig) -> Self {
+        Trace { policy: policy.to_string(), config, shadow: None, events: Vec::new() }
+    }
+
+    /// Function to parse a trace file written by Display
+    pub fn parse(source: &str) -> Result<Trace, String> {
+        let mut lines = source.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let header = lines.next().map(|(_, line)| line).unwrap_or("");
+        let header = header.strip_prefix("# trace ").ok_or("missing '# trace' header")?;
+
+        let mut trace = Trace::new("buddy", MemoryConfig::new());
+        for field in header.split_whitespace() {
+            let (key, value) = field.split_once('=').ok_or_else(|| format!("invalid header field '{}'", field))?;
+            let number = || parse_trace_number(Some(value), 1);
+            match key {
+                "policy" => trace.policy = value.to_string(),
+                "heap_size" => trace.config = trace.config.with_heap_size(number()?),
+                "min_block_size" => trace.config = trace.config.with_min_block_size(number()?),
+                "max_order" => trace.config = trace.config.with_max_order(number()?),
+                "redzone" => trace.shadow = Some(trace.shadow.unwrap_or_default().with_redzone(number()?)),
+                "quarantine" => trace.shadow = Some(trace.shadow.unwrap_or_default().with_quarantine(number()?)),
+                _ => return Err(format!("unknown header field '{}'", key)),
+            }
+        }
+
+        for (index, line) in lines {
+            let line_number = index + 1;
+            let (op, result) = line.split_once(" -> ").ok_or_else(|| format!("line {}: missing ' -> '", line_number))?;
+            let mut words = op.split_whitespace();
+            let seq = parse_trace_number(words.next(), line_number)?;
+            let name = words.next().unwrap_or("");
+            let args: Vec<&str> = words.collect();
+            let number = |index: usize| parse_trace_number(args.get(index).copied(), line_number);
+            let word = |index: usize| {
+                args.get(index).map(|word| word.to_string()).ok_or_else(|| format!("line {}: missing name", line_number))
+            };
+            let op = match name {
+                "insert" => TraceOp::Insert { size: number(0)? },
+                "set" => TraceOp::Set { id: number(0)?, len: number(1)? },
+                "update" => TraceOp::Update { id: number(0)?, len: number(1)? },
+                "update-stable" => TraceOp::UpdateStable { id: number(0)?, len: number(1)? },
+                "delete" => TraceOp::Delete { id: number(0)? },
+                "merge" => TraceOp::Merge,
+                "arena-insert" => TraceOp::ArenaInsert { size: number(0)? },
+                "arena-reset" => TraceOp::ArenaReset { mark: number(0)? },
+                "cache-create" => TraceOp::CacheCreate { name: word(0)?, object_size: number(1)? },
+                "cache-alloc" => TraceOp::CacheAlloc { name: word(0)? },
+                "cache-free" => TraceOp::CacheFree { name: word(0)?, id: number(1)? },
+                "policy" => TraceOp::Policy { name: word(0)? },
+                "flush-quarantine" => TraceOp::FlushQuarantine,
+                _ => return Err(format!("line {}: unknown operation '{}'", line_number, name)),
+            };
+            trace.events.push(TraceEvent { seq, op, result: parse_result(result, line_number)? });
+        }
+        Ok(trace)
+    }
+
+    /// Function to run every event of the trace on a new manager and check that each one gives the
+    /// recorded result, so blocks must land at the same addresses
+    /// It returns the number of events replayed, or a description of the first event that differs
+    pub fn replay(&self) -> Result<usize, String> {
+        let mut manager = MemoryManager::with_policy_name(self.config, &self.policy).map_err(|e| e.to_string())?;
+        if let Some(shadow) = self.shadow {
+            manager.enable_shadow(shadow).map_err(|e| e.to_string())?;
+        }
+        for event in &self.events {
+            let result = manager.apply_trace_op(&event.op);
+            if result != event.result {
+                return Err(format!("event {} ({}): expected {}, got {}", event.seq, event.op, event.result, result));
+            }
+        }
+        Ok(self.events.len())
+    }
+}
+
+/// Trace recording of the memory manager
+impl MemoryManager {
+    /// Function to start recording every mutation that can change where blocks go into a new trace
+    /// Recording should start on a fresh manager, so that the trace can be replayed from an empty heap.
+    /// While a trace is recorded, snapshots cannot be loaded and shadow memory cannot be enabled,
+    /// since a replay could not do the same
+    pub fn start_trace(&mut self) {
+        let mut trace = Trace::new(self.policy.name(), self.config);
+        trace.shadow = self.shadow_config();
+        self.trace = Some(trace);
+    }
+
+    /// Function to stop recording and return the trace recorded so far
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    /// Function to add an event to the trace, if one is being recorded
+    pub(crate) fn record(&mut self, op: TraceOp, result: TraceResult) {
+        if let Some(trace) = self.trace.as_mut() {
+            let seq = trace.events.len() + 1;
+            trace.events.push(TraceEvent { seq, op, result });
+        }
+    }
+
+    /// Function to describe the block holding the data of an insert or update
+    pub(crate) fn trace_block(&self, outcome: &Result<usize, MemoryError>) -> TraceResult {
+        match outcome {
+            Ok(id) => {
+                let block = &self.allocated_blocks[id];
+                TraceResult::Block { id: *id, start: block.start, size: block.size }
+            }
+            Err(e) => TraceResult::Failed(e.to_string()),
+        }
+    }
+
+    /// Function to describe the object handed out by a slab cache
+    pub(crate) fn trace_object(&self, name: &str, outcome: &Result<usize, MemoryError>) -> TraceResult {
+        match outcome {
+            Ok(id) => TraceResult::Block {
+                id: *id,
+                start: self.cache_object_address(name, *id).unwrap_or(0),
+                size: self.caches.get(name).map_or(0, |cache| cache.object_size),
+            },
+            Err(e) => TraceResult::Failed(e.to_string()),
+        }
+    }
+
+    /// Function to perform a traced operation again, with zero bytes standing in for the data
+    fn apply_trace_op(&mut self, op: &TraceOp) -> TraceResult {
+        match op {
+            TraceOp::Insert { size } => {
+                let outcome = self.insert(*size);
+                self.trace_block(&outcome)
+            }
+            TraceOp::Set { id, len } => TraceResult::of(&self.set(*id, &vec![0; *len])),
+            TraceOp::Update { id, len } => {
+                let outcome = self.update(*id, &vec![0; *len]);
+                self.trace_block(&outcome)
+            }
+            TraceOp::UpdateStable { id, len } => {
+                let outcome = self.update_stable(*id, &vec![0; *len]).map(|handle| handle.get_id());
+                self.trace_block(&outcome)
+            }
+            TraceOp::Delete { id } => TraceResult::of(&self.delete(*id)),
+            TraceOp::Merge => {
+                self.merge_free_blocks();
+                TraceResult::Done
+            }
+            TraceOp::ArenaInsert { size } => {
+                let outcome = self.arena_insert(*size);
+                self.trace_block(&outcome)
+            }
+            TraceOp::ArenaReset { mark } => TraceResult::of(&self.arena_reset(*mark)),
+            TraceOp::CacheCreate { name, object_size } => TraceResult::of(&self.cache_create(name, *object_size)),
+            TraceOp::CacheAlloc { name } => {
+                let outcome = self.cache_alloc(name);
+                self.trace_object(name, &outcome)
+            }
+            TraceOp::CacheFree { name, id } => TraceResult::of(&self.cache_free(name, *id)),
+            TraceOp::Policy { name } => TraceResult::of(&self.set_policy(name)),
+            TraceOp::FlushQuarantine => {
+                self.flush_quarantine();
+                TraceResult::Done
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+use super::MemoryManager;
+use super::free_block::FreeBlock;
+use super::stats::Usage;
+
+/// HeapCheck is the result of checking the invariants of the heap with `verify`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeapCheck {
+    pub allocated_blocks: usize,
+    pub free_blocks: usize,
+    pub violations: Vec<String>, // One description per broken invariant, empty for a sound heap
+}
+
+/// Implement HeapCheck struct
+impl HeapCheck {
+    /// Returns true if no invariant is broken
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Implement Display for HeapCheck
+impl fmt::Display for HeapCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} allocated and {} free block(s) checked, {} violation(s)",
+            self.allocated_blocks,
+            self.free_blocks,
+            self.violations.len()
+        )?;
+        for violation in &self.violations {
+            write!(f, "\n  - {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Heap verification of the memory manager
+impl MemoryManager {
+    /// Function to check every invariant of the heap and describe the ones that are broken
+    /// - the memory array is exactly as large as the heap
+    /// - each allocated block is stored under its own ID, below the next ID, and holds no more data than fits
+    /// - free blocks, allocated blocks and arena chunks cover the heap without gaps or overlaps
+    /// - the blocks follow the rules of the policy, such as alignment and merged buddies
+    /// - arena blocks lie in arena chunks and slab pages are allocated blocks
+    /// - the running usage totals match the allocated blocks
+    pub fn verify(&self) -> HeapCheck {
+        let mut violations = Vec::new();
+        let heap_size = self.config.get_heap_size();
+        if self.memory.len() != heap_size {
+            violations.push(format!("memory holds {} bytes but the heap has {}", self.memory.len(), heap_size));
+        }
+
+        let mut ids: Vec<&usize> = self.allocated_blocks.keys().collect();
+        ids.sort();
+        for &id in ids {
+            let block = &self.allocated_blocks[&id];
+            if block.id != id {
+                violations.push(format!("block stored under ID {} has ID {}", id, block.id));
+            }
+            if id >= self.next_id {
+                violations.push(format!("block {} is not below the next ID {}", id, self.next_id));
+            }
+            if block.data_size > block.size {
+                violations.push(format!("block {} holds {} bytes of data in {} bytes", id, block.data_size, block.size));
+            }
+        }
+
+        // Arena blocks lie inside the arena chunks, so the chunks take their place in the heap
+        let free_blocks = self.policy.free_blocks();
+        let mut policy_blocks: Vec<FreeBlock> = self
+            .allocated_blocks
+            .values()
+            .filter(|block| !self.is_arena_block(block.id))
+            .map(|block| FreeBlock::new(block.start, block.size))
+            .collect();
+        policy_blocks.extend(self.arena.chunks.iter().map(|chunk| FreeBlock::new(chunk.start, chunk.size)));
+        // Quarantined ranges are still taken from the policy until the quarantine gives them back
+        if let Some(shadow) = &self.shadow {
+            policy_blocks.extend(shadow.quarantine.iter().copied());
+        }
+        violations.extend(self.verify_tiling(&free_blocks, &policy_blocks));
+        violations.extend(self.policy.verify(&policy_blocks));
+
+        for id in &self.arena.blocks {
+            match self.allocated_blocks.get(id) {
+                Some(block) => {
+                    let inside = self.arena.chunks.iter().any(|chunk| {
+                        chunk.start <= block.start && block.start + block.size <= chunk.start + chunk.used
+                    });
+                    if !inside {
+                        violations.push(format!("arena block {} is outside the used part of the arena chunks", id));
+                    }
+                }
+                None => violations.push(format!("arena block {} is not allocated", id)),
+            }
+        }
+        for cache in self.caches.values() {
+            for slab in &cache.slabs {
+                if !self.allocated_blocks.contains_key(&slab.page_id) {
+                    violations.push(format!("page {} of slab cache '{}' is not allocated", slab.page_id, cache.name));
+                }
+            }
+        }
+
+        let counted = Usage::recount(&self.allocated_blocks);
+        let totals = |usage: &Usage| (usage.blocks, usage.allocated_bytes, usage.data_bytes);
+        if totals(&counted) != totals(&self.usage) {
+            violations.push(format!(
+                "usage counts {:?} (blocks, bytes, data) but the blocks add up to {:?}",
+                totals(&self.usage),
+                totals(&counted)
+            ));
+        }
+
+        HeapCheck { allocated_blocks: self.allocated_blocks.len(), free_blocks: free_blocks.len(), violations }
+    }
+
+    /// Function to check that the free blocks and the blocks allocated from the policy cover the heap
+    /// exactly once, reporting each gap, overlap and block past the end of the memory array
+    fn verify_tiling(&self, free_blocks: &[FreeBlock], policy_blocks: &[FreeBlock]) -> Vec<String> {
+        let mut violations = Vec::new();
+        let mut ranges: Vec<(usize, usize, bool)> = free_blocks.iter().map(|block| (block.start, block.size, true)).collect();
+        ranges.extend(policy_blocks.iter().map(|block| (block.start, block.size, false)));
+        ranges.sort();
+
+        let describe = |start: usize, size: usize, free: bool| {
+            format!("{} 0x{:04X}-0x{:04X}", if free { "free block" } else { "allocated block" }, start, start + size - 1)
+        };
+        let mut end = 0;
+        let mut previous = None;
+        for &(start, size, free) in &ranges {
+            if size == 0 {
+                violations.push(format!("{} at 0x{:04X} is empty", if free { "free block" } else { "allocated block" }, start));
+                continue;
+            }
+            if start > end {
+                violations.push(format!("gap of {} bytes at 0x{:04X}", start - end, end));
+            } else if start < end
+                && let Some((previous_start, previous_size, previous_free)) = previous
+            {
+                violations.push(format!(
+                    "{} overlaps {}",
+                    describe(start, size, free),
+                    describe(previous_start, previous_size, previous_free)
+                ));
+            }
+            if start + size > self.memory.len() {
+                violations.push(format!("{} runs past the end of memory ({} bytes)", describe(start, size, free), self.memory.len()));
+            }
+            if start + size > end {
+                end = start + size;
+                previous = Some((start, size, free));
+            }
+        }
+        if end < self.config.get_heap_size() {
+            violations.push(format!("gap of {} bytes at 0x{:04X}", self.config.get_heap_size() - end, end));
+        }
+        violations
+    }
+
+    /// Function to check the heap after every executed command, reporting broken invariants as a
+    /// failed CHECK next to the outcome of the command
+    pub fn set_verify_commands(&mut self, enabled: bool) {
+        self.verify_commands = enabled;
+    }
+}
@@ -0,0 +1,118 @@
+use super::MemoryManager;
+use super::error::MemoryError;
+use super::raw::Owner;
+
+/// Number of bytes shown on each line of a hexdump
+pub const HEXDUMP_WIDTH: usize = 16;
+
+/// Number of cells in the bar drawn by MAP
+pub const MAP_WIDTH: usize = 64;
+
+/// Function to name a region of the heap in the notes of a hexdump
+fn label(owner: &Owner) -> String {
+    match owner {
+        Owner::Allocated { id, .. } => format!("block {}", id),
+        Owner::Free { .. } => "free".to_string(),
+        Owner::ArenaChunk { .. } => "arena (unused)".to_string(),
+        Owner::Quarantined { .. } => "quarantined".to_string(),
+    }
+}
+
+/// Views of the heap
+impl MemoryManager {
+    /// Function to show `len` bytes of the heap from `start` the way `xxd -a` does
+    /// Each line notes the blocks starting on it, with their offset in the line and their size, and
+    /// runs of zero lines where no block starts are folded into a single '*'
+    pub fn hexdump(&self, start: usize, len: usize) -> Result<String, MemoryError> {
+        let heap_size = self.config.get_heap_size();
+        let end = start.checked_add(len).filter(|&end| end <= heap_size);
+        let end = end.ok_or(MemoryError::AddressOutOfRange { address: start, len, heap_size })?;
+        let regions = self.regions();
+
+        let mut lines = Vec::new();
+        let mut previous_zero = false;
+        let mut skipping = false;
+        for line_start in (start..end).step_by(HEXDUMP_WIDTH) {
+            let bytes = &self.memory[line_start..(line_start + HEXDUMP_WIDTH).min(end)];
+            let notes: Vec<String> = regions
+                .iter()
+                .filter_map(|owner| {
+                    let (region_start, size) = owner.range();
+                    if (line_start..line_start + bytes.len()).contains(&region_start) {
+                        Some(format!("{} at +{} ({} bytes)", label(owner), region_start - line_start, size))
+                    } else if line_start == start && region_start < start && start < region_start + size {
+                        // The first line also names the region it starts in
+                        Some(format!("in {}", label(owner)))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            let zero = bytes.iter().all(|&byte| byte == 0);
+            let last = line_start + bytes.len() == end;
+            if zero && previous_zero && notes.is_empty() && !last {
+                if !skipping {
+                    lines.push("*".to_string());
+                    skipping = true;
+                }
+                continue;
+            }
+            previous_zero = zero;
+            skipping = false;
+
+            let hex: Vec<String> = bytes.chunks(2).map(|pair| pair.iter().map(|byte| format!("{:02x}", byte)).collect()).collect();
+            let text: String =
+                bytes.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }).collect();
+            let line = format!(
+                "{:08x}: {:<hex_width$}  {:<text_width$}  {}",
+                line_start,
+                hex.join(" "),
+                text,
+                notes.join(", "),
+                hex_width = HEXDUMP_WIDTH / 2 * 5 - 1,
+                text_width = HEXDUMP_WIDTH
+            );
+            lines.push(line.trim_end().to_string());
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Function to draw the heap as a bar of MAP_WIDTH cells, one character per cell:
+    /// '#' when every byte of the cell holds data of an allocated block, '.' when every byte is
+    /// free, and '+' for anything in between, such as unused room at the end of a block
+    pub fn memory_map(&self) -> String {
+        let heap_size = self.config.get_heap_size();
+        let cells = MAP_WIDTH.min(heap_size);
+        let cell_size = heap_size / cells;
+        let mut data = vec![0; cells];
+        let mut free = vec![0; cells];
+        let count = |counts: &mut [usize], start: usize, len: usize| {
+            for (cell, count) in counts.iter_mut().enumerate().skip(start / cell_size) {
+                let cell_start = cell * cell_size;
+                if cell_start >= start + len {
+                    break;
+                }
+                *count += (start + len).min(cell_start + cell_size) - start.max(cell_start);
+            }
+        };
+        for block in self.allocated_blocks.values() {
+            count(&mut data, block.start, block.data_size);
+        }
+        for block in self.policy.free_blocks() {
+            count(&mut free, block.start, block.size);
+        }
+
+        let bar: String = (0..cells)
+            .map(|cell| match (data[cell], free[cell]) {
+                (used, _) if used == cell_size => '#',
+                (_, unused) if unused == cell_size => '.',
+                _ => '+',
+            })
+            .collect();
+        format!(
+            "Memory map: {} cells of {} bytes\n[{}]\n'#' in use, '+' partially used, '.' free",
+            cells, cell_size, bar
+        )
+    }
+}
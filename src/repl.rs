@@ -0,0 +1,234 @@
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+
+use crate::command::{Command, parse_line, parse_script};
+use crate::line_editor::LineEditor;
+use crate::memory_manager::MemoryManager;
+
+/// Prompt shown before each line
+const PROMPT: &str = "memory> ";
+
+/// Help text printed by the `help` command
+const HELP: &str = "\
+Memory manager commands:
+  INSERT <size> <data>      Allocate a block and store data in it
+  READ <id> [TEXT|HEX|JSON] Show a block
+  UPDATE <id> <data>        Replace the data of a block
+  UPDATE STABLE <id> <data> Same, but keep the ID if the block has to move
+  DELETE <id>               Free a block
+  DUMP [TEXT|HEX|JSON]      Show every allocated and free block
+  STATS [TEXT|JSON]         Show fragmentation, utilization and peaks
+  HEXDUMP [<addr> <n>]      Show the bytes of the heap, or n bytes from an address, like xxd
+  MAP                       Draw the heap as a bar of allocated, partially used and free cells
+  CHECK                     Verify the invariants of the heap
+  PEEK <addr> <n>           Show n raw bytes of the heap from an address
+  POKE <addr> <byte>...     Write byte values to the heap from an address
+  WRITE <addr> <data>       Write data to the heap from an address
+  WHOIS <addr>              Tell which block holds an address
+  ARENA INSERT <n> <data>   Allocate a short-lived block in the arena
+  ARENA MARK                Show the current arena mark
+  ARENA RESET <mark>        Free every arena block allocated after a mark
+  CACHE CREATE <name> <n>   Create a slab cache of <n>-byte objects
+  CACHE ALLOC <name>        Allocate an object from a slab cache
+  CACHE FREE <name> <id>    Give an object back to its slab cache
+  POLICY <name>             Switch allocation policy while no block is allocated
+  SAVE SNAPSHOT <file>      Write a snapshot of the whole heap to a file (SAVE <file> in scripts)
+  LOAD <file>               Restore the heap from a snapshot file
+  EXIT                      Leave the session
+Data can be plain text, a \"quoted string\" with escapes, 0x hex bytes or base64:...
+Addresses are decimal or 0x hex. PEEK, POKE and WRITE followed by STRICT refuse bytes outside a single allocated block.
+Session commands (the leading dot is optional):
+  .help                     Show this message
+  .history                  List the lines entered so far
+  !<n> / !!                 Run line <n> of the history again / run the last line again
+  .source <file>            Run the commands of a .cmmd file
+  .save <file>              Write the commands of this session to a .cmmd file
+  .quit                     Leave the session
+On a terminal, the arrow keys, Home, End, Backspace and Delete edit the line, and Up/Down recall the history.";
+
+/// Repl is an interactive session around a memory manager.
+/// It runs the same commands as a .cmmd script, keeps a history of the lines entered, and
+/// remembers every command executed so the session can be saved as a replayable script.
+pub struct Repl<W: Write> {
+    manager: MemoryManager,
+    output: W,
+    history: Vec<String>,
+    session: Vec<Command>,
+}
+
+/// Implement Repl struct
+impl<W: Write> Repl<W> {
+    /// Creates a new session that runs commands on the given manager and writes its messages to `output`
+    /// The outcome of each command is written to `output` too, so the manager should not have a sink
+    pub fn new(manager: MemoryManager, output: W) -> Self {
+        Repl {
+            manager,
+            output,
+            history: Vec::new(),
+            session: Vec::new(),
+        }
+    }
+
+    /// Returns the memory manager of the session
+    pub fn manager(&self) -> &MemoryManager {
+        &self.manager
+    }
+
+    /// Returns the lines entered so far
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Returns the commands executed so far
+    pub fn session(&self) -> &[Command] {
+        &self.session
+    }
+
+    /// Function to read lines from the input until it ends or the session is left
+    pub fn run<R: BufRead>(&mut self, mut input: R) -> io::Result<()> {
+        writeln!(self.output, "Type 'help' for a list of commands.")?;
+        loop {
+            write!(self.output, "{}", PROMPT)?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(self.output)?;
+                return Ok(());
+            }
+            if !self.handle_line(line.trim_end_matches(['\n', '\r']))? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Function to read key presses from a terminal in raw mode until the input ends or the session is left
+    /// Lines are edited with a LineEditor, which recalls the history of the session with Up and Down
+    pub fn run_editing<R: Read>(&mut self, mut input: R) -> io::Result<()> {
+        writeln!(self.output, "Type 'help' for a list of commands.")?;
+        loop {
+            let line = match LineEditor::new(PROMPT, &self.history).read_line(&mut input, &mut self.output)? {
+                Some(line) => line,
+                None => return Ok(()),
+            };
+            if !self.handle_line(&line)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Function to handle one line of input
+    /// Returns false once the session should end
+    pub fn handle_line(&mut self, line: &str) -> io::Result<bool> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return Ok(true);
+        }
+
+        // History expansion is resolved before the line itself is recorded
+        let line = if let Some(reference) = trimmed.strip_prefix('!') {
+            match self.history_entry(reference) {
+                Some(entry) => {
+                    writeln!(self.output, "{}", entry)?;
+                    entry
+                }
+                None => {
+                    writeln!(self.output, "No history entry '{}'", reference)?;
+                    return Ok(true);
+                }
+            }
+        } else {
+            trimmed.to_string()
+        };
+        self.history.push(line.clone());
+
+        let (word, argument) = match line.split_once(char::is_whitespace) {
+            Some((word, argument)) => (word, argument.trim()),
+            None => (line.as_str(), ""),
+        };
+        // Session commands may start with a dot, and `save snapshot <file>` is left to the SAVE command
+        let snapshot = argument.split_whitespace().next().is_some_and(|first| first.eq_ignore_ascii_case("snapshot"));
+        match word.to_lowercase().as_str() {
+            "help" | ".help" => writeln!(self.output, "{}", HELP)?,
+            "history" | ".history" => {
+                for (i, entry) in self.history.iter().enumerate() {
+                    writeln!(self.output, "{:>4}  {}", i + 1, entry)?;
+                }
+            }
+            "source" | ".source" if !argument.is_empty() => self.source(argument)?,
+            "save" | ".save" if !argument.is_empty() && !snapshot => self.save(argument)?,
+            "quit" | ".quit" => return Ok(false),
+            _ => match parse_line(&line, self.history.len()) {
+                Ok(commands) => {
+                    for command in commands {
+                        if !self.run_command(command)? {
+                            return Ok(false);
+                        }
+                    }
+                }
+                Err(e) => writeln!(self.output, "{}", e.in_file("<stdin>"))?,
+            },
+        }
+        Ok(true)
+    }
+
+    /// Function to find a history entry from the text following '!'
+    fn history_entry(&self, reference: &str) -> Option<String> {
+        let index = if reference == "!" {
+            self.history.len().checked_sub(1)?
+        } else {
+            reference.parse::<usize>().ok()?.checked_sub(1)?
+        };
+        self.history.get(index).cloned()
+    }
+
+    /// Function to execute a command on the manager, print its outcome and record it in the session
+    /// Returns false for EXIT, which ends the session
+    fn run_command(&mut self, command: Command) -> io::Result<bool> {
+        let outcome = self.manager.execute(&command);
+        writeln!(self.output, "{}", outcome)?;
+        if outcome.is_exit() {
+            return Ok(false);
+        }
+        self.session.push(command);
+        Ok(true)
+    }
+
+    /// Function to run every command of a .cmmd file
+    /// Nothing is run if the file has parse errors
+    fn source(&mut self, path: &str) -> io::Result<()> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => return writeln!(self.output, "Unable to read {}: {}", path, e),
+        };
+        match parse_script(path, &source) {
+            Ok(statements) => {
+                for statement in statements {
+                    if !self.run_command(statement.command)? {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+            Err(errors) => {
+                for e in errors {
+                    writeln!(self.output, "{}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Function to write the commands of the session to a .cmmd file
+    fn save(&mut self, path: &str) -> io::Result<()> {
+        let mut script = String::from("# Saved from an interactive session\n");
+        for command in &self.session {
+            script.push_str(&command.to_string());
+            script.push('\n');
+        }
+        match fs::write(path, script) {
+            Ok(()) => writeln!(self.output, "Saved {} command(s) to {}", self.session.len(), path),
+            Err(e) => writeln!(self.output, "Unable to write {}: {}", path, e),
+        }
+    }
+}
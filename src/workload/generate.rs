@@ -0,0 +1,302 @@
+use std::fmt;
+
+use crate::command::Command;
+use crate::memory_manager::MemoryManager;
+use crate::memory_manager::outcome::CommandOutcome;
+use crate::parse_count;
+
+use super::WorkloadReport;
+use super::rng::Rng;
+
+/// Largest number a distribution may give, so that generated blocks and lifetimes stay reasonable
+pub const MAX_SAMPLE: usize = 1 << 24;
+
+/// Distribution is a distribution of positive whole numbers, used for block sizes and lifetimes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Distribution {
+    /// `uniform:<min>-<max>`: every number between `min` and `max` is equally likely.
+    Uniform { min: usize, max: usize },
+    /// `exponential:<mean>`: small numbers are common and large ones rare, averaging `mean`.
+    Exponential { mean: f64 },
+    /// `bimodal:<small>,<large>,<share>`: numbers cluster around `small` and `large`, with `share`
+    /// of them around `large`. Each number is within half of its mode.
+    Bimodal { small: usize, large: usize, large_share: f64 },
+    /// `pow2:<min>-<max>`: mostly exact powers of two between `min` and `max`, with one in five
+    /// numbers anywhere in the range.
+    PowerOfTwo { min: usize, max: usize },
+}
+
+/// Function to parse a `<min>-<max>` range of a distribution
+fn parse_range(text: &str) -> Result<(usize, usize), String> {
+    let (min, max) = text.split_once('-').ok_or_else(|| format!("expected <min>-<max>, found '{}'", text))?;
+    let (min, max) = (parse_count(min)?, parse_count(max)?);
+    if min == 0 || min > max {
+        return Err(format!("invalid range '{}'", text));
+    }
+    if max > MAX_SAMPLE {
+        return Err(format!("range '{}' goes beyond {}", text, MAX_SAMPLE));
+    }
+    Ok((min, max))
+}
+
+/// Function to parse a decimal number of a workload option
+fn parse_real(text: &str) -> Result<f64, String> {
+    text.trim().parse().map_err(|_| format!("invalid number '{}'", text))
+}
+
+/// Implement Distribution enum
+impl Distribution {
+    /// Function to parse a distribution written as in its Display form, e.g. `exponential:64`
+    pub fn parse(text: &str) -> Result<Distribution, String> {
+        let (kind, parameters) = text.split_once(':').unwrap_or((text, ""));
+        let distribution = match kind.to_lowercase().as_str() {
+            "uniform" => {
+                let (min, max) = parse_range(parameters)?;
+                Distribution::Uniform { min, max }
+            }
+            "exponential" => Distribution::Exponential { mean: parse_real(parameters)? },
+            "bimodal" => match parameters.split(',').collect::<Vec<_>>()[..] {
+                [small, large, share] => Distribution::Bimodal {
+                    small: parse_count(small)?,
+                    large: parse_count(large)?,
+                    large_share: parse_real(share)?,
+                },
+                _ => return Err(format!("expected bimodal:<small>,<large>,<share>, found '{}'", text)),
+            },
+            "pow2" => {
+                let (min, max) = parse_range(parameters)?;
+                Distribution::PowerOfTwo { min, max }
+            }
+            _ => return Err(format!("unknown distribution '{}' (expected uniform, exponential, bimodal or pow2)", kind)),
+        };
+        match distribution {
+            Distribution::Exponential { mean } if mean.is_nan() || mean <= 0.0 || mean > MAX_SAMPLE as f64 => {
+                Err(format!("invalid mean in '{}'", text))
+            }
+            Distribution::Bimodal { small, large, large_share }
+                if small == 0 || large == 0 || small > MAX_SAMPLE || large > MAX_SAMPLE || !(0.0..=1.0).contains(&large_share) =>
+            {
+                Err(format!("invalid parameters in '{}'", text))
+            }
+            distribution => Ok(distribution),
+        }
+    }
+
+    /// Function to draw a number from the distribution, which is always at least 1 and at most MAX_SAMPLE
+    pub fn sample(&self, rng: &mut Rng) -> usize {
+        let value = match *self {
+            Distribution::Uniform { min, max } => rng.between(min, max),
+            Distribution::Exponential { mean } => (-mean * (1.0 - rng.next_f64()).ln()).ceil() as usize,
+            Distribution::Bimodal { small, large, large_share } => {
+                let mode = if rng.next_f64() < large_share { large } else { small };
+                rng.between(mode - mode / 2, mode + mode / 2)
+            }
+            Distribution::PowerOfTwo { min, max } => {
+                if rng.below(5) == 0 {
+                    rng.between(min, max)
+                } else {
+                    let (low, high) = (min.next_power_of_two().ilog2(), max.ilog2());
+                    if low > high { min } else { 1 << rng.between(low as usize, high as usize) }
+                }
+            }
+        };
+        value.clamp(1, MAX_SAMPLE)
+    }
+}
+
+/// Implement Display for Distribution
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Distribution::Uniform { min, max } => write!(f, "uniform:{}-{}", min, max),
+            Distribution::Exponential { mean } => write!(f, "exponential:{}", mean),
+            Distribution::Bimodal { small, large, large_share } => write!(f, "bimodal:{},{},{}", small, large, large_share),
+            Distribution::PowerOfTwo { min, max } => write!(f, "pow2:{}-{}", min, max),
+        }
+    }
+}
+
+/// OperationMix gives the relative weight of each kind of operation in a workload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OperationMix {
+    pub insert: u32,
+    pub update: u32,
+    pub read: u32,
+    pub delete: u32,
+}
+
+/// Kind of operation drawn from an OperationMix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operation {
+    Insert,
+    Update,
+    Read,
+    Delete,
+}
+
+/// Implement OperationMix struct
+impl OperationMix {
+    /// Function to parse a mix such as `insert=50,update=20,read=20,delete=10`
+    /// Operations that are not named get a weight of 0
+    pub fn parse(text: &str) -> Result<OperationMix, String> {
+        let mut mix = OperationMix { insert: 0, update: 0, read: 0, delete: 0 };
+        for part in text.split(',') {
+            let (name, weight) = part.split_once('=').ok_or_else(|| format!("expected <operation>=<weight>, found '{}'", part))?;
+            let weight = weight.trim().parse().map_err(|_| format!("invalid weight '{}'", weight))?;
+            match name.trim().to_lowercase().as_str() {
+                "insert" => mix.insert = weight,
+                "update" => mix.update = weight,
+                "read" => mix.read = weight,
+                "delete" => mix.delete = weight,
+                other => return Err(format!("unknown operation '{}' (expected insert, update, read or delete)", other)),
+            }
+        }
+        if mix.insert == 0 {
+            return Err("the mix needs inserts".to_string());
+        }
+        let weights = [mix.insert, mix.update, mix.read, mix.delete];
+        if weights.iter().try_fold(0u32, |total, &weight| total.checked_add(weight)).is_none() {
+            return Err(format!("the weights of '{}' add up to more than {}", text, u32::MAX));
+        }
+        Ok(mix)
+    }
+
+    /// Function to draw an operation with the weights of the mix
+    fn pick(&self, rng: &mut Rng) -> Operation {
+        let weights = [
+            (Operation::Insert, self.insert),
+            (Operation::Update, self.update),
+            (Operation::Read, self.read),
+            (Operation::Delete, self.delete),
+        ];
+        let total: u64 = weights.iter().map(|&(_, weight)| u64::from(weight)).sum();
+        let mut choice = rng.next_u64() % total.max(1);
+        for (operation, weight) in weights {
+            if choice < u64::from(weight) {
+                return operation;
+            }
+            choice -= u64::from(weight);
+        }
+        Operation::Insert
+    }
+}
+
+/// Implement Default for OperationMix
+impl Default for OperationMix {
+    fn default() -> Self {
+        OperationMix { insert: 50, update: 20, read: 20, delete: 10 }
+    }
+}
+
+/// Implement Display for OperationMix
+impl fmt::Display for OperationMix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "insert={},update={},read={},delete={}", self.insert, self.update, self.read, self.delete)
+    }
+}
+
+/// WorkloadSpec describes a synthetic workload, which is the same every time for the same seed.
+///
+/// Each step runs one command. A block is deleted as soon as its lifetime, counted in steps,
+/// runs out; otherwise the command is drawn from the operation mix, and updates, reads and
+/// deletes pick a random live block. Without lifetimes, blocks are only deleted by the mix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkloadSpec {
+    pub seed: u64,
+    pub operations: usize,
+    pub sizes: Distribution,
+    pub lifetimes: Option<Distribution>,
+    pub mix: OperationMix,
+}
+
+/// LiveBlock is a block inserted by a generated workload that has not been deleted yet.
+struct LiveBlock {
+    id: usize,
+    size: usize,
+    death: Option<usize>, // Step at which the block is deleted
+}
+
+/// Implement Default for WorkloadSpec
+impl Default for WorkloadSpec {
+    fn default() -> Self {
+        WorkloadSpec {
+            seed: 1,
+            operations: 1000,
+            sizes: Distribution::Uniform { min: 16, max: 256 },
+            lifetimes: Some(Distribution::Exponential { mean: 100.0 }),
+            mix: OperationMix::default(),
+        }
+    }
+}
+
+/// Implement WorkloadSpec struct
+impl WorkloadSpec {
+    /// Returns the name of the workload used in reports
+    pub fn name(&self) -> String {
+        format!("workload seed {}", self.seed)
+    }
+
+    /// Function to run the workload on a memory manager, passing every command to `emit` before
+    /// it is executed, e.g. to write it to a .cmmd script
+    /// The manager decides the IDs of the blocks, so a script written this way replays the same
+    /// workload only on a heap with the same configuration and policy
+    pub fn run(&self, manager: &mut MemoryManager, mut emit: impl FnMut(&Command)) -> WorkloadReport {
+        let mut rng = Rng::new(self.seed);
+        let mut report = WorkloadReport::new(&self.name(), manager);
+        let mut live: Vec<LiveBlock> = Vec::new();
+        let mut payload = 0;
+
+        for step in 0..self.operations {
+            let operation = match live.iter().position(|block| block.death.is_some_and(|death| death <= step)) {
+                Some(index) => {
+                    live.swap(0, index);
+                    Operation::Delete
+                }
+                None if live.is_empty() => Operation::Insert,
+                None => {
+                    let operation = self.mix.pick(&mut rng);
+                    if operation != Operation::Insert {
+                        let index = rng.below(live.len());
+                        live.swap(0, index);
+                    }
+                    operation
+                }
+            };
+
+            // Updates, reads and deletes act on the block moved to the front of `live`
+            let data = |size: usize| vec![b'a' + (step % 26) as u8; size];
+            let (command, size) = match operation {
+                Operation::Insert => {
+                    let size = self.sizes.sample(&mut rng);
+                    (Command::Insert { size, data: data(size) }, size)
+                }
+                Operation::Update => {
+                    let size = self.sizes.sample(&mut rng);
+                    (Command::Update { id: live[0].id, data: data(size) }, size)
+                }
+                Operation::Read => (Command::Read { id: live[0].id, format: None }, 0),
+                Operation::Delete => (Command::Delete { id: live[0].id }, 0),
+            };
+            emit(&command);
+            let outcome = manager.execute(&command);
+
+            match outcome {
+                CommandOutcome::Inserted { id } => {
+                    let death = self.lifetimes.as_ref().map(|lifetimes| step + lifetimes.sample(&mut rng));
+                    live.push(LiveBlock { id, size, death });
+                    payload += size;
+                }
+                CommandOutcome::Updated { new_id, .. } => {
+                    payload = payload - live[0].size + size;
+                    live[0].size = size;
+                    live[0].id = new_id.unwrap_or(live[0].id);
+                }
+                CommandOutcome::Deleted { .. } => payload -= live.swap_remove(0).size,
+                _ => {}
+            }
+            report.track_payload(payload);
+            report.record(manager, outcome.error().is_none());
+        }
+        report
+    }
+}
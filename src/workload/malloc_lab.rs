@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use crate::memory_manager::MemoryManager;
+use crate::parse_trace_number;
+
+use super::WorkloadReport;
+
+/// MallocOp is one request of a malloc/free trace, naming blocks by trace ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MallocOp {
+    /// `a <id> <size>`: allocates a block of `size` bytes.
+    Alloc { id: usize, size: usize },
+    /// `f <id>`: frees a block.
+    Free { id: usize },
+    /// `r <id> <size>`: resizes a block, which may move it.
+    Realloc { id: usize, size: usize },
+}
+
+/// MallocTrace is a recorded sequence of malloc, free and realloc calls, in the format of the
+/// CMU malloc-lab traces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MallocTrace {
+    pub name: String,
+    pub ops: Vec<MallocOp>,
+}
+
+/// Implement MallocTrace struct
+impl MallocTrace {
+    /// Function to parse a trace with one `a <id> <size>`, `f <id>` or `r <id> <size>` request per line
+    /// The lines holding a single number before the first request are the malloc-lab header
+    /// (suggested heap size, number of IDs, number of requests and weight) and are skipped,
+    /// as are blank lines and lines starting with '#'
+    pub fn parse(name: &str, source: &str) -> Result<MallocTrace, String> {
+        let mut ops = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let mut words = line.split_whitespace();
+            let Some(kind) = words.next().filter(|word| !word.starts_with('#')) else {
+                continue;
+            };
+            if ops.is_empty() && kind.parse::<f64>().is_ok() && words.clone().next().is_none() {
+                continue;
+            }
+            let mut number = || parse_trace_number(words.next(), line_number);
+            ops.push(match kind {
+                "a" => MallocOp::Alloc { id: number()?, size: number()? },
+                "f" => MallocOp::Free { id: number()? },
+                "r" => MallocOp::Realloc { id: number()?, size: number()? },
+                _ => return Err(format!("line {}: unknown request '{}'", line_number, kind)),
+            });
+            if let Some(extra) = words.next() {
+                return Err(format!("line {}: unexpected '{}'", line_number, extra));
+            }
+        }
+        Ok(MallocTrace { name: name.to_string(), ops })
+    }
+
+    /// Function to run the trace on a memory manager, with inserts, deletes and updates
+    /// Trace IDs are mapped to the IDs the manager gives out. Requests on a block whose allocation
+    /// failed are skipped, so one failure does not turn every later request on it into an error.
+    /// Allocations of a trace ID that is still in use are skipped too, rather than leaking its block
+    pub fn run(&self, manager: &mut MemoryManager) -> WorkloadReport {
+        let mut report = WorkloadReport::new(&self.name, manager);
+        let mut blocks: HashMap<usize, (usize, usize)> = HashMap::new(); // Manager ID and size by trace ID
+        let mut payload = 0;
+        for op in &self.ops {
+            let succeeded = match *op {
+                MallocOp::Alloc { id, .. } if blocks.contains_key(&id) => {
+                    report.skip();
+                    continue;
+                }
+                MallocOp::Alloc { id, size } => match manager.insert(size) {
+                    Ok(manager_id) => {
+                        blocks.insert(id, (manager_id, size));
+                        payload += size;
+                        true
+                    }
+                    Err(_) => false,
+                },
+                MallocOp::Free { id } => match blocks.remove(&id) {
+                    Some((manager_id, size)) => {
+                        payload -= size;
+                        manager.delete(manager_id).is_ok()
+                    }
+                    None => {
+                        report.skip();
+                        continue;
+                    }
+                },
+                MallocOp::Realloc { id, size } => match blocks.get_mut(&id) {
+                    Some(block) => match manager.update(block.0, &vec![0; size]) {
+                        Ok(new_id) => {
+                            payload = payload - block.1 + size;
+                            *block = (new_id, size);
+                            true
+                        }
+                        Err(_) => false,
+                    },
+                    None => {
+                        report.skip();
+                        continue;
+                    }
+                },
+            };
+            report.track_payload(payload);
+            report.record(manager, succeeded);
+        }
+        report
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt;
+
+use crate::memory_manager::MemoryManager;
+
+pub mod generate;
+pub mod malloc_lab;
+pub mod rng;
+
+pub use generate::{Distribution, MAX_SAMPLE, OperationMix, WorkloadSpec};
+pub use malloc_lab::{MallocOp, MallocTrace};
+pub use rng::Rng;
+
+/// WorkloadReport describes how an allocation policy coped with a workload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkloadReport {
+    pub name: String,
+    pub policy: &'static str,
+    pub operations: usize,
+    pub failed: usize,  // Operations the manager refused, mostly for lack of memory
+    pub skipped: usize, // Operations on blocks whose allocation had failed, and allocations of IDs in use
+    pub heap_size: usize,
+    pub peak_payload: usize, // Highest number of bytes requested by the blocks alive at once
+    pub internal_fragmentation: f64,     // Share of the allocated bytes not holding data, at the end
+    pub external_fragmentation: f64,     // 1 - largest free block / free bytes, at the end
+    pub max_external_fragmentation: f64, // Highest external fragmentation seen after any operation
+}
+
+/// Implement WorkloadReport struct
+impl WorkloadReport {
+    /// Creates an empty report for a workload about to run on the given manager
+    pub fn new(name: &str, manager: &MemoryManager) -> Self {
+        WorkloadReport {
+            name: name.to_string(),
+            policy: manager.policy_name(),
+            operations: 0,
+            failed: 0,
+            skipped: 0,
+            heap_size: manager.config().get_heap_size(),
+            peak_payload: 0,
+            internal_fragmentation: 0.0,
+            external_fragmentation: 0.0,
+            max_external_fragmentation: 0.0,
+        }
+    }
+
+    /// Function to count an operation and measure the heap after it
+    pub fn record(&mut self, manager: &MemoryManager, succeeded: bool) {
+        self.operations += 1;
+        if !succeeded {
+            self.failed += 1;
+        }
+        let stats = manager.stats();
+        self.internal_fragmentation = match stats.usage.allocated_bytes {
+            0 => 0.0,
+            allocated => stats.internal_fragmentation as f64 / allocated as f64,
+        };
+        self.external_fragmentation = stats.external_fragmentation;
+        self.max_external_fragmentation = self.max_external_fragmentation.max(stats.external_fragmentation);
+    }
+
+    /// Function to note the number of bytes requested by the blocks alive after an operation
+    pub fn track_payload(&mut self, payload: usize) {
+        self.peak_payload = self.peak_payload.max(payload);
+    }
+
+    /// Function to count an operation that could not be run
+    pub fn skip(&mut self) {
+        self.operations += 1;
+        self.skipped += 1;
+    }
+
+    /// Returns the number of operations that succeeded
+    pub fn get_succeeded(&self) -> usize {
+        self.operations - self.failed - self.skipped
+    }
+
+    /// Returns the share of the operations that succeeded, 1 for an empty workload
+    pub fn get_success_rate(&self) -> f64 {
+        if self.operations == 0 { 1.0 } else { self.get_succeeded() as f64 / self.operations as f64 }
+    }
+
+    /// Returns the highest payload alive at once, as a share of the heap
+    pub fn get_peak_utilization(&self) -> f64 {
+        self.peak_payload as f64 / self.heap_size as f64
+    }
+}
+
+/// Implement Display for WorkloadReport
+impl fmt::Display for WorkloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({}): {} operation(s), {} succeeded ({:.1}%), {} failed, {} skipped",
+            self.name,
+            self.policy,
+            self.operations,
+            self.get_succeeded(),
+            self.get_success_rate() * 100.0,
+            self.failed,
+            self.skipped
+        )?;
+        writeln!(
+            f,
+            "  Peak utilization: {:.1}% ({} of {} bytes of payload)",
+            self.get_peak_utilization() * 100.0,
+            self.peak_payload,
+            self.heap_size
+        )?;
+        write!(
+            f,
+            "  Fragmentation: internal {:.1}%, external {:.1}% (highest {:.1}%)",
+            self.internal_fragmentation * 100.0,
+            self.external_fragmentation * 100.0,
+            self.max_external_fragmentation * 100.0
+        )
+    }
+}
@@ -0,0 +1,41 @@
+/// Rng is a small seeded pseudo-random number generator (SplitMix64), so workloads can be
+/// generated again from their seed on any machine.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+/// Implement Rng struct
+impl Rng {
+    /// Creates a generator whose numbers depend only on the seed
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// Returns the next 64 random bits
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a number in [0, n), or 0 when n is 0
+    pub fn below(&mut self, n: usize) -> usize {
+        if n == 0 { 0 } else { (self.next_u64() % n as u64) as usize }
+    }
+
+    /// Returns a number in [min, max]
+    pub fn between(&mut self, min: usize, max: usize) -> usize {
+        match (max - min).checked_add(1) {
+            Some(span) => min + self.below(span),
+            None => self.next_u64() as usize, // The range is every usize
+        }
+    }
+}
@@ -1,4 +1,21 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use systems_project::command::{Command, parse_line, parse_script};
+use systems_project::compare::{Comparison, Workload};
 use systems_project::memory_manager::MemoryManager;
+use systems_project::{DataFormat, parse_data};
+use systems_project::repl::Repl;
+use systems_project::memory_manager::buddy::BuddyPolicy;
+use systems_project::memory_manager::config::MemoryConfig;
+use systems_project::memory_manager::error::MemoryError;
+use systems_project::memory_manager::outcome::CommandOutcome;
+use systems_project::memory_manager::free_block::FreeBlock;
+use systems_project::memory_manager::handle::{RETIRED_HISTORY, Staleness};
+use systems_project::memory_manager::shadow::{AccessError, ShadowConfig, ShadowState};
+use systems_project::memory_manager::policy::AllocationPolicy;
+use systems_project::memory_manager::trace::Trace;
+use systems_project::workload::{Distribution, MAX_SAMPLE, MallocOp, MallocTrace, OperationMix, Rng, WorkloadSpec};
 
 #[test]
 fn test_insert_and_read() {
@@ -55,6 +72,7 @@ fn test_insert_exact_fit() {
 }
 
 #[test]
+#[allow(clippy::useless_vec)] // Kept as written upstream
 fn test_insert_multiple_blocks() {
     let mut manager = MemoryManager::new();
     let id1 = manager.insert(500).unwrap();
@@ -123,3 +141,1068 @@ fn test_memory_full() {
     assert!(mm.insert(1024).is_err(), "Should fail once memory is full");
 }
 
+
+/// Policy that wraps the buddy policy and counts the calls made to it
+struct CountingPolicy {
+    inner: BuddyPolicy,
+    allocations: Rc<Cell<usize>>,
+}
+
+impl AllocationPolicy for CountingPolicy {
+    fn name(&self) -> &'static str {
+        "counting"
+    }
+
+    fn block_size(&self, requested: usize) -> usize {
+        self.inner.block_size(requested)
+    }
+
+    fn allocate(&mut self, requested: usize) -> Option<FreeBlock> {
+        self.allocations.set(self.allocations.get() + 1);
+        self.inner.allocate(requested)
+    }
+
+    fn release(&mut self, block: FreeBlock) {
+        self.inner.release(block)
+    }
+
+    fn merge_free_blocks(&mut self) {
+        self.inner.merge_free_blocks()
+    }
+
+    fn free_blocks(&self) -> Vec<FreeBlock> {
+        self.inner.free_blocks()
+    }
+
+    fn restore_free_blocks(&mut self, free_blocks: &[FreeBlock]) -> Result<(), String> {
+        self.inner.restore_free_blocks(free_blocks)
+    }
+}
+
+/// LeakyPolicy is a buddy policy that forgets the blocks given back to it
+struct LeakyPolicy(BuddyPolicy);
+impl AllocationPolicy for LeakyPolicy {
+    fn name(&self) -> &'static str {
+        "leaky"
+    }
+
+    fn block_size(&self, requested: usize) -> usize {
+        self.0.block_size(requested)
+    }
+
+    fn allocate(&mut self, requested: usize) -> Option<FreeBlock> {
+        self.0.allocate(requested)
+    }
+
+    fn release(&mut self, _block: FreeBlock) {}
+
+    fn merge_free_blocks(&mut self) {}
+
+    fn free_blocks(&self) -> Vec<FreeBlock> {
+        self.0.free_blocks()
+    }
+
+    fn restore_free_blocks(&mut self, free_blocks: &[FreeBlock]) -> Result<(), String> {
+        self.0.restore_free_blocks(free_blocks)
+    }
+}
+
+#[test]
+fn test_custom_policy() {
+    let allocations = Rc::new(Cell::new(0));
+    let policy = CountingPolicy { inner: BuddyPolicy::new(65536).unwrap(), allocations: allocations.clone() };
+    let mut mm = MemoryManager::with_policy(MemoryConfig::new(), Box::new(policy)).unwrap();
+    assert_eq!(mm.policy_name(), "counting");
+
+    let id = mm.insert(5).unwrap();
+    mm.set(id, b"Hello").unwrap();
+    mm.execute_command("INSERT 3 abc");
+    assert_eq!(allocations.get(), 2);
+    assert!(mm.read_formatted(id).unwrap().contains("Hello"));
+}
+
+#[test]
+fn test_buddy_split_and_coalesce() {
+    let mut buddy = BuddyPolicy::new(65536).unwrap();
+    let a = buddy.allocate(8).unwrap();
+    let b = buddy.allocate(8).unwrap();
+    let c = buddy.allocate(100).unwrap();
+    assert_eq!((a.start, a.size), (0, 8));
+    assert_eq!((b.start, b.size), (8, 8));
+    assert_eq!((c.start, c.size), (128, 128));
+
+    // One free block per order from 16 up to 32768 is left, except 128 which went to c
+    assert_eq!(buddy.free_blocks().len(), 11);
+
+    buddy.release(a);
+    buddy.release(c);
+    buddy.release(b);
+    let free = buddy.free_blocks();
+    assert_eq!(free.len(), 1);
+    assert_eq!((free[0].start, free[0].size), (0, 65536));
+}
+
+#[test]
+fn test_buddy_reuses_smallest_block() {
+    let mut buddy = BuddyPolicy::new(65536).unwrap();
+    let a = buddy.allocate(16).unwrap();
+    let _b = buddy.allocate(16).unwrap();
+    buddy.release(a);
+
+    // The freed 16-byte block is preferred over splitting a larger one
+    let c = buddy.allocate(10).unwrap();
+    assert_eq!((c.start, c.size), (0, 16));
+}
+
+#[test]
+fn test_config_rejects_non_power_of_two() {
+    assert!(MemoryManager::with_config(MemoryConfig::new().with_heap_size(3000)).is_err());
+    assert!(MemoryManager::with_config(MemoryConfig::new().with_min_block_size(24)).is_err());
+    assert!(MemoryManager::with_config(MemoryConfig::new().with_heap_size(4096).with_max_order(13)).is_err());
+    assert!(MemoryManager::with_config(MemoryConfig::new().with_heap_size(4096).with_min_block_size(16).with_max_order(8)).is_ok());
+    assert!(BuddyPolicy::new(3000).is_err());
+}
+
+#[test]
+fn test_small_heap_with_min_block() {
+    let config = MemoryConfig::new().with_heap_size(4096).with_min_block_size(64);
+    let mut mm = MemoryManager::with_config(config).unwrap();
+
+    // Every block takes at least 64 bytes, so only 64 of them fit
+    for _ in 0..64 {
+        let id = mm.insert(1).unwrap();
+        mm.set(id, b"x").unwrap();
+    }
+    assert!(mm.insert(1).is_err());
+}
+
+#[test]
+fn test_max_order_limits_block_size() {
+    let config = MemoryConfig::new().with_heap_size(1 << 20).with_max_order(12);
+    let mut mm = MemoryManager::with_config(config).unwrap();
+    assert!(mm.insert(8192).is_err(), "Blocks larger than 4 KiB should be rejected");
+
+    // The 1 MiB heap is made of 256 separate 4 KiB blocks that never merge
+    for _ in 0..256 {
+        mm.insert(4096).unwrap();
+    }
+    assert!(mm.insert(1).is_err());
+}
+
+#[test]
+fn test_full_heap_block_is_writable() {
+    let mut mm = MemoryManager::new();
+    let id = mm.insert(65536).unwrap();
+    assert_eq!(mm.set(id, &[7u8; 65536]), Ok(()));
+}
+
+#[test]
+fn test_error_kinds() {
+    let config = MemoryConfig::new().with_heap_size(4096);
+    let mut mm = MemoryManager::with_config(config).unwrap();
+
+    assert_eq!(mm.insert(0), Err(MemoryError::ZeroSize));
+    assert_eq!(mm.delete(7), Err(MemoryError::UnknownId(7)));
+    assert_eq!(mm.read_formatted(7).unwrap_err(), MemoryError::UnknownId(7));
+
+    let id = mm.insert(3000).unwrap();
+    assert_eq!(
+        mm.insert(3000),
+        Err(MemoryError::OutOfMemory { requested: 3000, largest_free: 0 })
+    );
+    assert_eq!(
+        mm.set(id, &[0u8; 5000]),
+        Err(MemoryError::DataTooLarge { data_size: 5000, block_size: 4096 })
+    );
+    assert!(matches!(
+        MemoryManager::with_config(MemoryConfig::new().with_heap_size(1000)),
+        Err(MemoryError::InvalidConfig(_))
+    ));
+}
+
+#[test]
+fn test_parse_preserves_payload_case() {
+    let commands = parse_line("insert 12 Hello  World;", 1).unwrap();
+    assert_eq!(commands, vec![Command::Insert { size: 12, data: b"Hello  World".to_vec() }]);
+
+    let mut mm = MemoryManager::new();
+    mm.execute_command("INSERT 12 MiXeD case");
+    assert!(mm.read_formatted(0).unwrap().contains("'MiXeD case'"));
+}
+
+#[test]
+fn test_parse_quoted_strings_and_statements() {
+    let commands = parse_line(r#"UPDATE 3 "say \"hi\"; now"; READ 3"#, 1).unwrap();
+    assert_eq!(
+        commands,
+        vec![
+            Command::Update { id: 3, data: b"say \"hi\"; now".to_vec() },
+            Command::Read { id: 3, format: None },
+        ]
+    );
+
+    // Displayed commands parse back to the same command
+    let reparsed = parse_line(&commands[0].to_string(), 1).unwrap();
+    assert_eq!(reparsed[0], commands[0]);
+
+    assert!(parse_line("   # a comment", 1).unwrap().is_empty());
+    assert!(parse_line("", 1).unwrap().is_empty());
+}
+
+#[test]
+fn test_parse_errors_have_positions() {
+    let error = parse_line("DELETE abc", 4).unwrap_err();
+    assert_eq!((error.line, error.column), (4, 8));
+    assert_eq!(error.message, "invalid block ID 'abc'");
+
+    let errors = parse_script("demo.cmmd", "DUMP\nINSERT 4\nFROB 1\n").unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].to_string(), "demo.cmmd:2:9: missing data");
+    assert_eq!(errors[1].to_string(), "demo.cmmd:3:1: unknown command 'FROB'");
+}
+
+#[test]
+fn test_invalid_id_is_not_zero() {
+    let mut mm = MemoryManager::new();
+    mm.execute_command("INSERT 4 keep");
+    mm.execute_command("DELETE x");
+    assert!(mm.read(0).is_ok(), "An invalid ID must not delete block 0");
+}
+
+#[test]
+fn test_parse_data_forms() {
+    assert_eq!(parse_data("0x48 65 6C6C 6F"), Ok(b"Hello".to_vec()));
+    assert_eq!(parse_data("base64:SGVsbG8="), Ok(b"Hello".to_vec()));
+    assert_eq!(parse_data("base64:SGVsbG8"), Ok(b"Hello".to_vec()));
+    assert_eq!(parse_data(r#""a\tb\x00\xFF""#), Ok(vec![b'a', b'\t', b'b', 0, 0xFF]));
+    assert_eq!(parse_data("plain text"), Ok(b"plain text".to_vec()));
+
+    assert!(parse_data("0xZZ").is_err(), "Bad hex must be an error, not a panic");
+    assert!(parse_data("0x123").is_err());
+    assert!(parse_data("base64:SG!s").is_err());
+    assert!(parse_data(r#""bad \q""#).is_err());
+}
+
+#[test]
+fn test_binary_payload_round_trip() {
+    let mut mm = MemoryManager::new();
+    mm.execute_command("INSERT 4 0xDE AD 00 EF");
+    let hex = mm.read_formatted_as(0, DataFormat::Hex).unwrap();
+    assert!(hex.ends_with("Data: 0xDE AD 00 EF"));
+
+    // The hex shown by READ can be fed back to UPDATE
+    let data = hex.split("Data: ").nth(1).unwrap();
+    mm.execute_command(&format!("UPDATE 0 {}", data));
+    assert_eq!(mm.read_formatted_as(0, DataFormat::Hex).unwrap(), hex);
+
+    let commands = parse_line(r#"UPDATE 0 "line\nbreak"; DUMP HEX"#, 1).unwrap();
+    assert_eq!(commands[0], Command::Update { id: 0, data: b"line\nbreak".to_vec() });
+    assert_eq!(commands[1], Command::Dump { format: Some(DataFormat::Hex) });
+    assert_eq!(parse_line(&commands[0].to_string(), 1).unwrap()[0], commands[0]);
+
+    let error = parse_line("INSERT 4 0xG1", 1).unwrap_err();
+    assert_eq!(error.column, 10);
+}
+
+#[test]
+fn test_empty_payload() {
+    let mut mm = MemoryManager::new();
+    for line in [r#"INSERT 4 """#, "INSERT 4 0x", "UPDATE 0 base64:"] {
+        let error = parse_line(line, 1).unwrap_err();
+        assert!(error.to_string().contains("data must not be empty"), "{}", error);
+        mm.execute_command(line);
+    }
+    assert!(mm.execute_command("READ 0").to_string().starts_with("READ error"));
+
+    // Blocks emptied through the API are still readable
+    let id = mm.insert(4).unwrap();
+    mm.set(id, &[]).unwrap();
+    assert!(mm.read_formatted(id).unwrap().ends_with("Size: 0 bytes, Data: ''"));
+}
+
+#[test]
+fn test_repl_session_save_and_source() {
+    let path = std::env::temp_dir().join(format!("repl_session_{}.cmmd", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let input = format!("INSERT 5 Hello\nhistory\n!1\nsave {}\nquit\nINSERT 1 never\n", path);
+    let mut output = Vec::new();
+    let mut repl = Repl::new(MemoryManager::new(), &mut output);
+    repl.run(input.as_bytes()).unwrap();
+    assert_eq!(repl.session().len(), 2);
+    assert_eq!(repl.history()[2], "INSERT 5 Hello");
+    assert!(repl.manager().read(1).is_ok());
+    assert!(repl.manager().read(2).is_err(), "Nothing runs after quit");
+
+    // Replaying the saved script rebuilds the same blocks
+    let mut replay = Repl::new(MemoryManager::new(), Vec::new());
+    replay.handle_line(&format!("source {}", path)).unwrap();
+    assert_eq!(replay.session(), repl.session());
+    assert_eq!(replay.manager().read_formatted(1), repl.manager().read_formatted(1));
+    std::fs::remove_file(path).unwrap();
+
+    let text = String::from_utf8(output).unwrap();
+    assert!(text.contains("   1  INSERT 5 Hello"));
+}
+
+#[test]
+fn test_repl_line_editing() {
+    // Left three times, type 'e', Enter; then Up recalls the line, Backspace and '!' edit it; Ctrl-D ends the input
+    let input = b"INSERT 5 Hllo\x1b[D\x1b[D\x1b[De\r\x1b[A\x7f!\r\x1b[A\x1b[A\x1b[B\x05\x15\x04";
+    let mut output = Vec::new();
+    let mut repl = Repl::new(MemoryManager::new(), &mut output);
+    repl.run_editing(&input[..]).unwrap();
+    assert_eq!(repl.history(), ["INSERT 5 Hello", "INSERT 5 Hell!"]);
+    assert!(repl.manager().read_formatted(1).unwrap().ends_with("Data: 'Hell!'"));
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("\rmemory> INSERT 5 Hello\x1b[K"));
+}
+
+#[test]
+fn test_json_state() {
+    let mut mm = MemoryManager::with_config(MemoryConfig::new().with_heap_size(64)).unwrap();
+    mm.execute_command(r#"INSERT 12 "He said \"hi\"""#);
+    mm.execute_command("INSERT 20 0x00 FF");
+
+    let json = mm.to_json();
+    assert!(json.starts_with(r#"{"config":{"heap_size":64,"min_block_size":1,"max_order":6},"policy":"buddy","#));
+    assert!(json.contains(r#""counters":{"next_id":2,"allocated_blocks":2,"allocated_bytes":48,"data_bytes":14,"#));
+    assert!(json.contains(r#"{"id":0,"start":0,"end":15,"size":16,"data_size":12,"data":"He said \"hi\"","#));
+    assert!(json.contains(r#""data_hex":"00FF"}"#));
+    assert!(json.contains(r#""free_blocks":[{"start":16,"end":31,"size":16}],"caches":[]"#));
+    assert!(json.ends_with(r#""arena":{"blocks":0,"used_bytes":0,"chunks":[]}}"#));
+
+    assert_eq!(
+        mm.read_formatted_as(1, DataFormat::Json).unwrap(),
+        "{\"id\":1,\"start\":32,\"end\":63,\"size\":32,\"data_size\":2,\"data\":\"\\u0000\u{FFFD}\",\"data_hex\":\"00FF\"}"
+    );
+    assert_eq!(parse_line("dump json", 1).unwrap()[0], Command::Dump { format: Some(DataFormat::Json) });
+}
+
+#[test]
+fn test_command_outcomes_and_sink() {
+    let mut mm = MemoryManager::with_config(MemoryConfig::new().with_heap_size(64)).unwrap();
+    assert_eq!(mm.execute_command("INSERT 4 abcd"), CommandOutcome::Inserted { id: 0 });
+    assert_eq!(mm.execute_command("UPDATE 0 abcdefgh"), CommandOutcome::Updated { id: 0, new_id: Some(1) });
+    assert_eq!(mm.execute_command("UPDATE 1 xy"), CommandOutcome::Updated { id: 1, new_id: None });
+    assert_eq!(
+        mm.execute_command("INSERT 100 big").error(),
+        Some(&MemoryError::OutOfMemory { requested: 100, largest_free: 32 })
+    );
+    assert_eq!(mm.execute_command(""), CommandOutcome::Batch(Vec::new()));
+
+    // Nothing is printed until a sink is installed, then every outcome is rendered through it
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let captured = lines.clone();
+    mm.set_sink(Box::new(move |outcome: &CommandOutcome| captured.borrow_mut().push(outcome.to_string())));
+    mm.execute_command("DELETE 1; DELETE 1");
+    mm.execute_command("EXIT");
+    assert_eq!(
+        *lines.borrow(),
+        vec![
+            "DELETE success: ID = 1\nDELETE error: Block 1#0 was freed".to_string(),
+            "Exiting...".to_string(),
+        ]
+    );
+    assert_eq!(mm.dump(), "Memory Dump:\n0x0000 - 0x003F: FREE (Size: 64 bytes)");
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let config = MemoryConfig::new().with_heap_size(256).with_min_block_size(8);
+    let mut mm = MemoryManager::with_config(config).unwrap();
+    mm.execute_command("INSERT 5 Hello; INSERT 20 0xDE AD BE EF; INSERT 9 gone");
+    mm.execute_command("DELETE 2");
+    let snapshot = mm.to_snapshot();
+
+    let mut restored = MemoryManager::new();
+    restored.restore_snapshot(&snapshot).unwrap();
+    assert_eq!(restored.dump(), mm.dump());
+    assert_eq!(restored.config().get_heap_size(), 256);
+    assert_eq!(restored.to_snapshot(), snapshot);
+
+    // IDs keep counting from where the snapshot left off
+    assert_eq!(restored.insert(4), Ok(3));
+
+    // SAVE and LOAD go through a file
+    let path = std::env::temp_dir().join(format!("snapshot_{}.bin", std::process::id()));
+    let path = path.to_str().unwrap();
+    let command = Command::Save { path: path.to_string() };
+    assert_eq!(mm.execute(&command), CommandOutcome::Saved { path: path.to_string() });
+    let mut loaded = MemoryManager::new();
+    assert_eq!(loaded.execute_command(&format!("LOAD \"{}\"", path)), CommandOutcome::Loaded { path: path.to_string() });
+    assert_eq!(loaded.dump(), mm.dump());
+    std::fs::remove_file(path).unwrap();
+
+    // In the REPL, `save <file>` writes the session script and SAVE SNAPSHOT writes the heap
+    let mut repl = Repl::new(mm, Vec::new());
+    repl.handle_line(&format!("save snapshot \"{}\"", path)).unwrap();
+    let mut loaded = MemoryManager::new();
+    loaded.load_snapshot(path).unwrap();
+    assert_eq!(loaded.dump(), repl.manager().dump());
+    repl.handle_line(&format!("save {}", path)).unwrap();
+    assert!(std::fs::read_to_string(path).unwrap().starts_with("# Saved from an interactive session"));
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Function to build a version 3 snapshot of a 64-byte buddy heap with the given blocks and a valid checksum
+fn forged_snapshot(free: &[(u64, u64)], allocated: &[(u64, u64)]) -> Vec<u8> {
+    let mut bytes = b"CMMSNAP\0".to_vec();
+    bytes.extend_from_slice(&3u32.to_le_bytes());
+    let push = |bytes: &mut Vec<u8>, fields: &[u64]| fields.iter().for_each(|field| bytes.extend_from_slice(&field.to_le_bytes()));
+    push(&mut bytes, &[64, 1, 6, 5]);
+    bytes.extend_from_slice(b"buddy");
+    push(&mut bytes, &[allocated.len() as u64, 64]);
+    bytes.extend_from_slice(&[0; 64]);
+    push(&mut bytes, &[free.len() as u64]);
+    free.iter().for_each(|&(start, size)| push(&mut bytes, &[start, size]));
+    push(&mut bytes, &[allocated.len() as u64]);
+    allocated.iter().enumerate().for_each(|(id, &(start, size))| push(&mut bytes, &[id as u64, start, size, 0]));
+    push(&mut bytes, &[0, 0, 0]); // No slab caches, arena chunks or arena blocks
+
+    let mut crc = !0u32;
+    for &byte in &bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    bytes.extend_from_slice(&(!crc).to_le_bytes());
+    bytes
+}
+
+#[test]
+fn test_snapshot_rejects_forged_blocks() {
+    let mut mm = MemoryManager::with_config(MemoryConfig::new().with_heap_size(64)).unwrap();
+    let valid = forged_snapshot(&[(8, 8), (16, 16), (32, 32)], &[(0, 8)]);
+    assert_eq!(mm.restore_snapshot(&valid), Ok(()));
+
+    // Sizes that overflow an address are rejected instead of panicking
+    let overflowing = forged_snapshot(&[(8, u64::MAX)], &[(0, 8)]);
+    assert!(matches!(mm.restore_snapshot(&overflowing), Err(MemoryError::InvalidSnapshot(reason)) if reason.contains("does not fit")));
+    let overflowing = forged_snapshot(&[(0, 64)], &[(u64::MAX - 3, 8)]);
+    assert!(matches!(mm.restore_snapshot(&overflowing), Err(MemoryError::InvalidSnapshot(_))));
+
+    // The blocks tile the heap, but the allocated one is not aligned to its size
+    let misaligned = forged_snapshot(&[(0, 8), (24, 8), (32, 32)], &[(8, 16)]);
+    let error = mm.restore_snapshot(&misaligned).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid snapshot: allocated block at 0x0008 (16 bytes) is not aligned to its size");
+}
+
+#[test]
+fn test_snapshot_rejects_damaged_data() {
+    let mut mm = MemoryManager::with_config(MemoryConfig::new().with_heap_size(64)).unwrap();
+    mm.execute_command("INSERT 5 Hello");
+    let snapshot = mm.to_snapshot();
+    let before = mm.dump();
+
+    let mut flipped = snapshot.clone();
+    flipped[40] ^= 1;
+    assert!(matches!(mm.restore_snapshot(&flipped), Err(MemoryError::InvalidSnapshot(reason)) if reason.contains("checksum")));
+    assert!(matches!(mm.restore_snapshot(&snapshot[..20]), Err(MemoryError::InvalidSnapshot(_))));
+    assert!(matches!(mm.restore_snapshot(b"not a snapshot"), Err(MemoryError::InvalidSnapshot(_))));
+    assert_eq!(mm.dump(), before, "A rejected snapshot leaves the manager untouched");
+
+    assert!(matches!(mm.execute_command("LOAD /nonexistent/snapshot.bin").error(), Some(MemoryError::Io(_))));
+    assert!(parse_line("SAVE", 1).is_err());
+    assert_eq!(parse_line("save \"my heap.bin\"", 1).unwrap()[0].to_string(), "SAVE \"my heap.bin\"");
+}
+
+#[test]
+fn test_fit_policies_place_blocks() {
+    // Free ranges of 16, 8 and 8 bytes at 0x08, 0x20 and 0x38, separated by allocated blocks
+    let layout = |name: &str| {
+        let mut mm = MemoryManager::with_policy_name(MemoryConfig::new().with_heap_size(64), name).unwrap();
+        for size in [8, 16, 8, 8, 8, 8] {
+            mm.insert(size).unwrap();
+        }
+        mm.delete(1).unwrap();
+        mm.delete(3).unwrap();
+        mm
+    };
+    let placed = |mm: &mut MemoryManager, size| {
+        let id = mm.insert(size).unwrap();
+        mm.read(id).unwrap().start
+    };
+
+    let mut best = layout("best-fit");
+    assert_eq!(placed(&mut best, 5), 0x20);
+    assert_eq!(best.policy_name(), "best-fit");
+    let mut worst = layout("worst-fit");
+    assert_eq!(placed(&mut worst, 5), 0x08);
+    let mut next = layout("next-fit");
+    assert_eq!(placed(&mut next, 5), 0x38, "Next-fit starts after the last block");
+    assert_eq!(placed(&mut next, 5), 0x08, "Next-fit wraps around");
+    assert_eq!(placed(&mut next, 3), 0x0D);
+
+    // Sizes are not rounded to powers of two, and freed ranges merge with both neighbours
+    let mut mm = MemoryManager::with_policy_name(MemoryConfig::new().with_heap_size(64), "best-fit").unwrap();
+    let ids: Vec<usize> = [5, 7, 9].iter().map(|&size| mm.insert(size).unwrap()).collect();
+    assert_eq!(mm.read(ids[2]).unwrap().start, 12);
+    mm.delete(ids[0]).unwrap();
+    mm.delete(ids[2]).unwrap();
+    mm.delete(ids[1]).unwrap();
+    assert_eq!(mm.dump(), "Memory Dump:\n0x0000 - 0x003F: FREE (Size: 64 bytes)");
+    assert!(matches!(MemoryManager::with_policy_name(MemoryConfig::new(), "first-fit"), Err(MemoryError::UnknownPolicy(_))));
+}
+
+#[test]
+fn test_policy_command() {
+    let script = "# Compare with buddy\nPOLICY Worst-Fit\nINSERT 3 abc\nINSERT 3 def\nDUMP\n";
+    let statements = parse_script("policy.cmmd", script).unwrap();
+    assert_eq!(statements[0].command, Command::Policy { name: "worst-fit".to_string() });
+    assert_eq!(statements[0].command.to_string(), "POLICY worst-fit");
+    let errors = parse_script("bad.cmmd", "POLICY slab").unwrap_err();
+    assert!(errors[0].to_string().starts_with("bad.cmmd:1:8: unknown policy 'slab'"));
+
+    let mut mm = MemoryManager::with_config(MemoryConfig::new().with_heap_size(64)).unwrap();
+    for statement in &statements {
+        mm.execute(&statement.command);
+    }
+    assert_eq!(mm.policy_name(), "worst-fit");
+    assert!(mm.dump().contains("0x0003 - 0x0005: ALLOCATED (ID: 1) (Size: 3 bytes)"));
+
+    // The policy cannot change under allocated blocks
+    let outcome = mm.execute_command("POLICY buddy");
+    assert!(matches!(outcome.error(), Some(MemoryError::InvalidCommand(_))));
+    mm.execute_command("DELETE 0; DELETE 1");
+    assert_eq!(mm.execute_command("POLICY buddy").to_string(), "POLICY success: buddy");
+}
+
+#[test]
+fn test_slab_cache() {
+    let config = MemoryConfig::new().with_heap_size(1024).with_min_block_size(16);
+    let mut mm = MemoryManager::with_config(config).unwrap();
+    assert_eq!(
+        mm.execute_command("CACHE CREATE node 24").to_string(),
+        "CACHE CREATE success: node (Object size: 24 bytes)"
+    );
+
+    // A 256-byte page holds ten 24-byte objects, placed one after the other
+    let ids: Vec<usize> = (0..12).map(|_| mm.cache_alloc("node").unwrap()).collect();
+    assert_eq!(mm.cache_object_address("node", ids[1]), Ok(24));
+    assert_eq!(mm.cache_object_address("node", ids[10]), Ok(256));
+    let stats = &mm.cache_stats()[0];
+    assert_eq!((stats.full_slabs, stats.partial_slabs, stats.empty_slabs), (1, 1, 0));
+    assert_eq!((stats.objects_in_use, stats.total_objects, stats.page_bytes), (12, 20, 512));
+
+    // Freed slots are reused, and a page cannot be freed behind the cache's back
+    mm.cache_free("node", ids[3]).unwrap();
+    let reused = mm.cache_alloc("node").unwrap();
+    assert_eq!(mm.cache_object_address("node", reused), Ok(72));
+    assert!(matches!(mm.delete(ids[0] - 1), Err(MemoryError::SlabPage { .. })));
+    assert!(matches!(mm.cache_free("node", ids[3]), Err(MemoryError::StaleHandle { staleness: Staleness::Freed, .. })));
+    assert_eq!(mm.cache_free("leaf", 0), Err(MemoryError::UnknownCache("leaf".to_string())));
+    assert_eq!(mm.cache_create("node", 8), Err(MemoryError::CacheExists("node".to_string())));
+
+    let dump = mm.dump();
+    assert!(dump.contains("0x0000 - 0x00FF: ALLOCATED (ID: 0) (Size: 256 bytes) Slab: node"));
+    assert!(dump.ends_with("Slab caches:\n  node: object size 24 bytes, 12/20 objects in use, slabs 1 full / 1 partial / 0 empty"));
+    assert!(mm.to_json().contains(r#""caches":[{"name":"node","object_size":24,"full_slabs":1"#));
+
+    // Caches survive a snapshot
+    let mut restored = MemoryManager::new();
+    restored.restore_snapshot(&mm.to_snapshot()).unwrap();
+    assert_eq!(restored.dump(), dump);
+
+    // Emptying the second slab keeps it for later, and emptying both gives one page back to the heap
+    for &id in &ids[10..] {
+        mm.cache_free("node", id).unwrap();
+    }
+    assert_eq!(mm.cache_stats()[0].empty_slabs, 1);
+    for id in (0..10).map(|i| ids[i]).filter(|&id| id != ids[3]).chain([reused]) {
+        mm.cache_free("node", id).unwrap();
+    }
+    let stats = &mm.cache_stats()[0];
+    assert_eq!((stats.empty_slabs, stats.page_bytes), (1, 256));
+
+    let commands = parse_line("CACHE CREATE leaf 8; cache alloc leaf; CACHE FREE leaf 14", 1).unwrap();
+    assert_eq!(commands[2], Command::CacheFree { name: "leaf".to_string(), id: 14 });
+    assert_eq!(commands[1].to_string(), "CACHE ALLOC leaf");
+    assert!(parse_line("CACHE DESTROY leaf", 1).is_err());
+    assert!(parse_line("CACHE ALLOC leaf 3", 1).is_err());
+}
+
+#[test]
+fn test_tlsf_policy() {
+    let config = MemoryConfig::new().with_heap_size(1024).with_min_block_size(4);
+    let mut mm = MemoryManager::with_policy_name(config, "tlsf").unwrap();
+    let ids: Vec<usize> = [100, 30, 200, 30, 50].iter().map(|&size| mm.insert(size).unwrap()).collect();
+    assert_eq!(mm.read(ids[1]).unwrap().start, 100);
+    assert_eq!(mm.read(ids[2]).unwrap().start, 132);
+
+    // A freed block is reused by a request of its size class, and the tail of the heap stays whole
+    mm.delete(ids[2]).unwrap();
+    let id = mm.insert(190).unwrap();
+    assert_eq!(mm.read(id).unwrap().start, 132);
+    assert_eq!(mm.largest_free_block(), 1024 - 100 - 32 - 200 - 32 - 52);
+
+    // Every allocation takes at most two bitmap lookups, whatever the number of free blocks
+    let counters = mm.policy_counters();
+    assert_eq!((counters.allocations, counters.releases, counters.splits), (6, 1, 6));
+    assert!(counters.search_steps <= 2 * counters.allocations);
+
+    // Freeing everything merges the heap back into one block
+    for id in [ids[0], ids[1], ids[3], ids[4], id] {
+        mm.delete(id).unwrap();
+    }
+    assert_eq!(mm.dump(), "Memory Dump:\n0x0000 - 0x03FF: FREE (Size: 1024 bytes)");
+    assert!(matches!(mm.insert(1025), Err(MemoryError::OutOfMemory { .. })));
+    assert_eq!(mm.policy_counters().failed_allocations, 1);
+
+    // The same script runs on every policy, so their work can be compared
+    let script = parse_script("mix.cmmd", "INSERT 24 a\nINSERT 20 b\nDELETE 0\nINSERT 8 c\nUPDATE 1 0x00 11 22 33 44 55 66 77 88 99 AA BB CC DD EE FF 00 11 22 33 44 55 66 77 88 99 AA BB CC DD EE FF 00 11 22 33 44 55 66 77 88 99 AA BB CC DD EE FF\n").unwrap();
+    for policy in ["buddy", "tlsf"] {
+        let mut mm = MemoryManager::with_policy_name(MemoryConfig::new().with_heap_size(256), policy).unwrap();
+        for statement in &script {
+            assert!(mm.execute(&statement.command).error().is_none(), "{} failed {}", policy, statement.command);
+        }
+        assert_eq!(mm.policy_counters().allocations, 4);
+        assert!(mm.to_json().contains(r#""operations":{"allocations":4,"failed_allocations":0,"releases":2,"#));
+    }
+}
+
+#[test]
+fn test_bitmap_buddy_matches_buddy() {
+    // Both buddy backends run the same pseudo-random workload and must place every block at the same address
+    for config in [
+        MemoryConfig::new().with_heap_size(4096),
+        MemoryConfig::new().with_heap_size(2048).with_min_block_size(16).with_max_order(4),
+    ] {
+        let mut sets = MemoryManager::with_policy_name(config, "buddy").unwrap();
+        let mut bitmap = MemoryManager::with_policy_name(config, "buddy-bitmap").unwrap();
+        assert_eq!(bitmap.policy_name(), "buddy-bitmap");
+
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        let mut live = Vec::new();
+        for step in 0..2000 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let roll = (seed >> 33) as usize;
+            if live.is_empty() || !roll.is_multiple_of(3) {
+                let size = 1 + (roll >> 4) % 300;
+                let (a, b) = (sets.insert(size), bitmap.insert(size));
+                assert_eq!(a.is_ok(), b.is_ok(), "step {}", step);
+                if let (Ok(a), Ok(b)) = (a, b) {
+                    assert_eq!(sets.read(a).unwrap().start, bitmap.read(b).unwrap().start, "step {}", step);
+                    live.push(a);
+                }
+            } else {
+                let id = live.swap_remove((roll >> 4) % live.len());
+                sets.delete(id).unwrap();
+                bitmap.delete(id).unwrap();
+            }
+        }
+        assert_eq!(sets.dump(), bitmap.dump());
+        assert_eq!(sets.policy_counters().splits, bitmap.policy_counters().splits);
+        assert_eq!(sets.policy_counters().merges, bitmap.policy_counters().merges);
+
+        // A snapshot of one backend can be loaded into the other
+        let mut restored = MemoryManager::with_policy_name(config, "buddy").unwrap();
+        restored.restore_snapshot(&bitmap.to_snapshot()).unwrap();
+        assert_eq!(restored.policy_name(), "buddy-bitmap");
+        assert_eq!(restored.dump(), bitmap.dump());
+    }
+}
+
+#[test]
+fn test_arena_mark_and_reset() {
+    let mut mm = MemoryManager::with_config(MemoryConfig::new().with_heap_size(1024)).unwrap();
+    let long_lived = mm.insert(100).unwrap();
+
+    // Arena blocks are placed one after the other in a chunk of an eighth of the heap
+    let outcomes = mm.execute_command("ARENA INSERT 5 Hello; ARENA MARK; ARENA INSERT 7 scratch; ARENA INSERT 3 tmp");
+    assert_eq!(
+        outcomes.to_string(),
+        "ARENA INSERT success: ID = 1\nARENA MARK success: mark = 1\nARENA INSERT success: ID = 2\nARENA INSERT success: ID = 3"
+    );
+    let chunk = mm.arena().get_chunks()[0];
+    assert_eq!((chunk.size, chunk.used), (128, 15));
+    assert_eq!(mm.read(2).unwrap().start, chunk.start + 5);
+    assert!(mm.read_formatted(3).unwrap().contains("'tmp'"));
+
+    // Arena blocks are updated in place and never freed one by one
+    assert_eq!(mm.update(2, b"again"), Ok(2));
+    assert!(matches!(mm.update(2, b"much too long"), Err(MemoryError::DataTooLarge { .. })));
+    assert_eq!(mm.delete(2), Err(MemoryError::ArenaBlock(2)));
+
+    // A full chunk is followed by a new one, and the snapshot keeps both
+    let big = mm.arena_insert(120).unwrap();
+    assert_eq!(mm.arena().get_chunks().len(), 2);
+    assert!(mm.dump().ends_with("Arena: 4 block(s), 135 of 256 bytes used in 2 chunk(s)"));
+    let mut restored = MemoryManager::new();
+    restored.restore_snapshot(&mm.to_snapshot()).unwrap();
+    assert_eq!(restored.dump(), mm.dump());
+    assert_eq!(restored.delete(big), Err(MemoryError::ArenaBlock(big)));
+
+    // Resetting to the mark frees the later blocks and gives the emptied chunk back
+    assert_eq!(mm.execute_command("ARENA RESET 1").to_string(), "ARENA RESET success: mark = 1, freed 3 block(s)");
+    assert!(mm.read(2).is_err() && mm.read(1).is_ok());
+    assert_eq!(mm.arena().get_chunks().len(), 1);
+    assert_eq!(mm.arena().get_used_bytes(), 5);
+    assert_eq!(mm.arena_reset(4), Err(MemoryError::InvalidMark { mark: 4, top: 1 }));
+    assert_eq!(mm.arena_reset(0), Ok(1));
+    assert!(mm.arena().get_chunks().is_empty());
+    mm.delete(long_lived).unwrap();
+    assert_eq!(mm.dump(), "Memory Dump:\n0x0000 - 0x03FF: FREE (Size: 1024 bytes)");
+
+    assert_eq!(parse_line("arena reset 3", 1).unwrap()[0], Command::ArenaReset { mark: 3 });
+    assert!(parse_line("ARENA MARK 3", 1).is_err());
+}
+
+#[test]
+fn test_heap_stats() {
+    let mut mm = MemoryManager::with_config(MemoryConfig::new().with_heap_size(256)).unwrap();
+    mm.execute_command("INSERT 5 Hello; INSERT 20 0x01 02; INSERT 64 big");
+
+    let stats = mm.stats();
+    assert_eq!((stats.usage.blocks, stats.usage.allocated_bytes, stats.usage.data_bytes), (3, 104, 10));
+    assert_eq!(stats.internal_fragmentation, 94);
+    assert_eq!((stats.free_bytes, stats.largest_free_block), (152, 128));
+    assert!((stats.external_fragmentation - (1.0 - 128.0 / 152.0)).abs() < 1e-9);
+    assert!((stats.utilization - 104.0 / 256.0).abs() < 1e-9);
+    assert_eq!(stats.free_blocks_by_order.iter().collect::<Vec<_>>(), [(&3, &1), (&4, &1), (&7, &1)]);
+
+    // Peaks and the high-water mark stay where they were after blocks are freed
+    mm.execute_command("UPDATE 1 a longer payload; DELETE 2");
+    let stats = mm.stats();
+    assert_eq!((stats.usage.blocks, stats.usage.allocated_bytes, stats.usage.data_bytes), (2, 40, 21));
+    assert_eq!((stats.usage.peak_blocks, stats.usage.peak_allocated_bytes, stats.usage.peak_data_bytes), (3, 104, 24));
+    assert_eq!(stats.usage.high_water_mark, 128);
+    mm.execute_command("DELETE 0; DELETE 1");
+    let stats = mm.stats();
+    assert_eq!((stats.external_fragmentation, stats.utilization), (0.0, 0.0));
+    assert_eq!(stats.free_blocks_by_order.iter().collect::<Vec<_>>(), [(&8, &1)]);
+
+    let report = mm.execute_command("STATS").to_string();
+    assert!(report.starts_with("Heap Stats:\n  Heap size: 256 bytes\n  Allocated: 0 block(s), 0 bytes, 0 bytes of data\n"));
+    assert!(report.contains("  Free blocks of order 8: 1\n  Peak: 3 block(s), 104 bytes allocated, 24 bytes of data\n"));
+    assert!(report.ends_with("High-water mark: 0x0080"));
+    let json = mm.execute_command("STATS JSON").to_string();
+    assert!(json.contains(r#""free_blocks_by_order":[{"order":8,"count":1}],"peak_blocks":3,"#));
+}
+
+#[test]
+fn test_trace_record_and_replay() {
+    let config = MemoryConfig::new().with_heap_size(256).with_min_block_size(8);
+    let mut mm = MemoryManager::with_policy_name(config, "best-fit").unwrap();
+    mm.start_trace();
+    mm.execute_command("INSERT 20 first; INSERT 40 second; UPDATE 0 this payload is too long to fit; DELETE 1; INSERT 300 big");
+    mm.merge_free_blocks();
+    let trace = mm.take_trace().unwrap();
+
+    let text = trace.to_string();
+    assert!(text.starts_with("# trace policy=best-fit heap_size=256 min_block_size=8 max_order=5\n1 insert 20 -> id=0 addr=0x0000 size=24\n2 set 0 5 -> ok\n"));
+    assert!(text.contains("\n5 update 0 31 -> id=2 addr=0x0040 size=32\n6 delete 1 -> ok\n"));
+    assert!(text.ends_with("7 insert 300 -> error: No suitable block available: requested 300 bytes, largest free block is 160 bytes\n8 merge -> ok\n"));
+
+    // The trace survives a round trip through its text form and replays with the same addresses
+    let parsed = Trace::parse(&text).unwrap();
+    assert_eq!((parsed.policy.as_str(), &parsed.events), ("best-fit", &trace.events));
+    assert_eq!(parsed.replay(), Ok(8));
+
+    // A different policy places the blocks elsewhere, which the replay reports
+    let moved = Trace::parse(&text.replace("policy=best-fit", "policy=buddy")).unwrap();
+    let error = moved.replay().unwrap_err();
+    assert!(error.starts_with("event 1 (insert 20): expected id=0 addr=0x0000 size=24, got id=0 addr=0x0000 size=32"), "{}", error);
+    assert!(Trace::parse("1 insert 20 -> ok").is_err());
+}
+
+#[test]
+fn test_trace_covers_arena_caches_and_shadow() {
+    let mut mm = MemoryManager::with_config(MemoryConfig::new().with_heap_size(4096)).unwrap();
+    mm.enable_shadow(ShadowConfig::new().with_redzone(8).with_quarantine(64)).unwrap();
+    mm.start_trace();
+    mm.execute_command("ARENA INSERT 8 aa; INSERT 8 bb; DELETE 1; CACHE CREATE obj 24; CACHE ALLOC obj; CACHE FREE obj 3");
+    mm.execute_command("ARENA RESET 0; INSERT 100 cc");
+    mm.flush_quarantine();
+    assert!(mm.enable_shadow(ShadowConfig::new()).is_err(), "The trace header could not tell");
+    assert!(matches!(mm.restore_snapshot(&mm.to_snapshot()), Err(MemoryError::InvalidCommand(_))));
+    let trace = mm.take_trace().unwrap();
+
+    // Arena and cache operations consume IDs and move later blocks, so they are recorded too
+    let text = trace.to_string();
+    assert!(text.starts_with("# trace policy=buddy heap_size=4096 min_block_size=1 max_order=12 redzone=8 quarantine=64\n1 arena-insert 8 -> "));
+    assert!(text.contains(" cache-create obj 24 -> ok\n"));
+    assert!(text.contains(" cache-alloc obj -> id=3 addr=0x0400 size=24\n"));
+    assert!(text.contains(" arena-reset 0 -> ok\n"));
+    assert!(text.ends_with(" flush-quarantine -> ok\n"));
+    let parsed = Trace::parse(&text).unwrap();
+    assert_eq!((parsed.shadow, &parsed.events), (trace.shadow, &trace.events));
+    assert_eq!(parsed.replay(), Ok(trace.events.len()));
+
+    // Without the shadow settings the redzones are gone and the blocks land elsewhere
+    let header = text.lines().next().unwrap();
+    let bare = text.replace(header, "# trace policy=buddy heap_size=4096 min_block_size=1 max_order=12");
+    assert!(Trace::parse(&bare).unwrap().replay().is_err());
+}
+
+#[test]
+fn test_malloc_trace_import() {
+    let source = "20000\n4\n9\n1\na 0 512\na 1 128\nr 0 640\na 2 128\nf 1\n# a block too large for the heap\na 3 100000\nf 3\nf 0\nr 2 8\n";
+    let trace = MallocTrace::parse("short.rep", source).unwrap();
+    assert_eq!(trace.ops.len(), 9);
+    assert_eq!(trace.ops[2], MallocOp::Realloc { id: 0, size: 640 });
+
+    let mut mm = MemoryManager::with_config(MemoryConfig::new().with_heap_size(4096)).unwrap();
+    let report = trace.run(&mut mm);
+    assert_eq!((report.operations, report.get_succeeded(), report.failed, report.skipped), (9, 7, 1, 1));
+    assert_eq!(report.peak_payload, 896);
+    assert!((report.get_peak_utilization() - 896.0 / 4096.0).abs() < 1e-9);
+    // Trace IDs are remapped: trace block 2 is manager block 3, since block 0 moved to ID 2 when it grew
+    assert!(mm.read_formatted(3).unwrap().contains("Start Address: 0x0280"));
+    assert_eq!(report.internal_fragmentation, 120.0 / 128.0);
+    assert!(report.to_string().starts_with("short.rep (buddy): 9 operation(s), 7 succeeded (77.8%), 1 failed, 1 skipped\n"));
+
+    assert_eq!(MallocTrace::parse("bad.rep", "a 0 16\nx 1").unwrap_err(), "line 2: unknown request 'x'");
+    assert_eq!(MallocTrace::parse("bad.rep", "f").unwrap_err(), "line 1: missing number");
+    assert_eq!(MallocTrace::parse("bad.rep", "a 0 x").unwrap_err(), "line 1: invalid number 'x'");
+
+    // Allocating a trace ID that is still in use is skipped instead of leaking the first block
+    let trace = MallocTrace::parse("twice.rep", "a 0 16\na 0 32\nf 0\n").unwrap();
+    let mut mm = MemoryManager::new();
+    let report = trace.run(&mut mm);
+    assert_eq!((report.get_succeeded(), report.skipped), (2, 1));
+    assert_eq!(mm.stats().usage.blocks, 0);
+}
+
+#[test]
+fn test_generated_workload() {
+    let spec = WorkloadSpec {
+        seed: 42,
+        operations: 500,
+        sizes: Distribution::parse("bimodal:16,512,0.2").unwrap(),
+        lifetimes: Some(Distribution::parse("uniform:5-50").unwrap()),
+        mix: OperationMix::parse("insert=60,update=20,read=10,delete=10").unwrap(),
+    };
+    let config = MemoryConfig::new().with_heap_size(4096);
+
+    // The same seed gives the same commands, and the script does the same as driving the manager
+    let mut commands = Vec::new();
+    let report = spec.run(&mut MemoryManager::with_config(config).unwrap(), |command| commands.push(command.to_string()));
+    let mut again = Vec::new();
+    assert_eq!(spec.run(&mut MemoryManager::with_config(config).unwrap(), |command| again.push(command.to_string())), report);
+    assert_eq!(commands, again);
+    assert_eq!(commands.len(), 500);
+
+    let statements = parse_script("generated.cmmd", &commands.join("\n")).unwrap();
+    let mut mm = MemoryManager::with_config(config).unwrap();
+    let failed = statements.iter().filter(|statement| mm.execute(&statement.command).error().is_some()).count();
+    assert_eq!(failed, report.failed);
+    assert!(report.get_peak_utilization() > 0.0 && report.get_peak_utilization() <= 1.0);
+
+    let mut rng = Rng::new(7);
+    let pow2 = Distribution::parse("pow2:8-1024").unwrap();
+    let sizes: Vec<usize> = (0..1000).map(|_| pow2.sample(&mut rng)).collect();
+    assert!(sizes.iter().all(|&size| (8..=1024).contains(&size)));
+    assert!(sizes.iter().filter(|size| size.is_power_of_two()).count() > 750);
+    assert_eq!(Distribution::parse("exponential:64").unwrap().to_string(), "exponential:64");
+    assert!(Distribution::parse("uniform:10-5").is_err());
+    assert!(OperationMix::parse("read=1").is_err());
+
+    // Numbers that would overflow are parse errors instead of panics
+    assert!(Distribution::parse("uniform:1-18446744073709551615").is_err());
+    assert!(Distribution::parse("bimodal:16,18446744073709551615,0.5").is_err());
+    assert!(Distribution::parse("exponential:1e300").is_err());
+    assert!(OperationMix::parse("insert=4294967295,update=1").is_err());
+    let mix = OperationMix::parse("insert=4294967295").unwrap();
+    let spec = WorkloadSpec { operations: 10, mix, ..WorkloadSpec::default() };
+    assert_eq!(spec.run(&mut MemoryManager::with_config(MemoryConfig::new().with_heap_size(1024)).unwrap(), |_| {}).operations, 10);
+    let wide = Distribution::Uniform { min: 1, max: usize::MAX };
+    assert!((0..100).all(|_| (1..=MAX_SAMPLE).contains(&wide.sample(&mut rng))));
+    rng.between(0, usize::MAX); // Covers every usize without overflowing
+}
+
+#[test]
+fn test_compare_policies() {
+    let script = "INSERT 100 a; INSERT 30 b; INSERT 200 c; DELETE 1; INSERT 40 d; INSERT 900 e; EXIT; INSERT 10 f";
+    let commands = parse_script("compare.cmmd", script).unwrap().into_iter().map(|statement| statement.command).collect();
+    let workload = Workload::Script { name: "compare.cmmd".to_string(), commands };
+    let config = MemoryConfig::new().with_heap_size(1024);
+    let comparison = Comparison::run(config, &workload, &["buddy", "buddy-bitmap", "best-fit"]).unwrap();
+
+    let results = &comparison.results;
+    assert_eq!(results.iter().map(|result| result.policy).collect::<Vec<_>>(), ["buddy", "buddy-bitmap", "best-fit"]);
+    // The buddy policies place blocks alike, so they only differ in how they search
+    assert_eq!((results[0].failed_allocations, results[0].peak_allocated_bytes, results[0].high_water_mark), (1, 448, 512));
+    assert_eq!((results[1].failed_allocations, results[1].peak_allocated_bytes, results[1].high_water_mark), (1, 448, 512));
+    assert_eq!(results[0].external_fragmentation, results[1].external_fragmentation);
+    assert_eq!((results[2].failed_allocations, results[2].peak_allocated_bytes), (1, 340));
+    assert!(results.iter().all(|result| result.average_search_steps > 0.0));
+
+    let csv = comparison.to_csv();
+    assert_eq!(csv.lines().count(), 4);
+    assert!(csv.starts_with("policy,failed_allocations,peak_allocated_bytes,high_water_mark,external_fragmentation,average_search_steps,wall_clock_ms\nbuddy,1,448,512,"));
+    assert!(comparison.to_string().starts_with("Comparison of compare.cmmd:\nPolicy"));
+    assert!(Comparison::run(config, &workload, &["first-fit"]).is_err());
+}
+
+#[test]
+fn test_verify_heap() {
+    for name in ["buddy", "buddy-bitmap", "best-fit", "next-fit", "tlsf"] {
+        let mut mm = MemoryManager::with_policy_name(MemoryConfig::new().with_heap_size(1024), name).unwrap();
+        mm.execute_command("INSERT 100 a; INSERT 30 b; ARENA INSERT 10 c; CACHE CREATE small 16; CACHE ALLOC small; DELETE 0");
+        let check = mm.verify();
+        assert!(check.is_ok(), "{}: {}", name, check);
+        assert!(mm.execute_command("CHECK").to_string().starts_with("CHECK success: 3 allocated and "));
+    }
+
+    // A block the policy forgot about leaves a gap in the heap
+    let config = MemoryConfig::new().with_heap_size(256);
+    let mut mm = MemoryManager::with_policy(config, Box::new(LeakyPolicy(BuddyPolicy::new(256).unwrap()))).unwrap();
+    mm.execute_command("INSERT 30 a; INSERT 30 b; DELETE 1");
+    assert_eq!(mm.verify().violations, ["gap of 32 bytes at 0x0020"]);
+    let outcome = mm.execute_command("CHECK");
+    assert_eq!(outcome.error(), Some(&MemoryError::HeapCorrupted(vec!["gap of 32 bytes at 0x0020".to_string()])));
+    assert_eq!(outcome.to_string(), "CHECK error: Heap check found 1 violation(s): gap of 32 bytes at 0x0020");
+
+    // Once enabled, the check runs after every command and its failure follows the outcome
+    mm.set_verify_commands(true);
+    let outcome = mm.execute_command("DELETE 0");
+    assert_eq!(outcome.to_string(), "DELETE success: ID = 0\nCHECK error: Heap check found 1 violation(s): gap of 64 bytes at 0x0000");
+}
+
+#[test]
+fn test_handles() {
+    let mut mm = MemoryManager::with_policy_name(MemoryConfig::new().with_heap_size(1024), "buddy").unwrap();
+    mm.execute_command("INSERT 10 a; INSERT 10 b; INSERT 10 c");
+    let first = mm.handle(0).unwrap();
+    assert_eq!((first.get_id(), first.get_generation(), first.to_string()), (0, 0, "0#0".to_string()));
+    assert_eq!(mm.resolve(first), Ok(0));
+
+    // UPDATE STABLE keeps the ID and bumps the generation when the block moves
+    assert_eq!(mm.execute_command("UPDATE STABLE 0 short").to_string(), "UPDATE success: ID = 0");
+    assert_eq!(mm.execute_command("UPDATE STABLE 0 \"a much longer payload\"").to_string(), "UPDATE success: ID = 0, reallocated as 0#1");
+    assert_eq!(mm.read_formatted(0).unwrap().split("Data: ").nth(1), Some("'a much longer payload'"));
+    assert_eq!(mm.resolve(first).unwrap_err().to_string(), "Block 0#0 was moved and is now 0#1");
+    assert_eq!(mm.resolve(mm.handle(0).unwrap()), Ok(0));
+    assert!(mm.verify().is_ok());
+
+    // A plain UPDATE that moves the block retires the old ID, and deleted IDs stay known as freed
+    let outcome = mm.execute_command("UPDATE 1 \"another long payload\"; DELETE 2");
+    assert_eq!(outcome.to_string(), "UPDATE success: ID = 1, reallocated with new ID: 3\nDELETE success: ID = 2");
+    assert_eq!(mm.read(1).unwrap_err().to_string(), "Block 1#0 was moved and is now 3#0");
+    assert!(matches!(mm.delete(2), Err(MemoryError::StaleHandle { staleness: Staleness::Freed, .. })));
+    assert_eq!(mm.execute_command("UPDATE STABLE 2 x").to_string(), "UPDATE error: Block 2#0 was freed");
+    assert_eq!(mm.delete(99), Err(MemoryError::UnknownId(99)));
+
+    // Freeing a moved block makes every handle to it stale, and the script form round-trips
+    mm.delete(0).unwrap();
+    assert_eq!(mm.resolve(first).unwrap_err().to_string(), "Block 0#0 was freed");
+    let command = parse_line("update stable 3 \"x y\"", 1).unwrap().remove(0);
+    assert_eq!(command, Command::UpdateStable { id: 3, data: b"x y".to_vec() });
+    assert_eq!(command.to_string(), "UPDATE STABLE 3 \"x y\"");
+
+    // Only the most recent retired IDs are remembered, so long runs do not grow the history without bound
+    let mut mm = MemoryManager::with_policy_name(MemoryConfig::new().with_heap_size(1024), "best-fit").unwrap();
+    for id in 0..=RETIRED_HISTORY {
+        assert_eq!(mm.insert(8), Ok(id));
+        mm.delete(id).unwrap();
+    }
+    assert_eq!(mm.delete(0), Err(MemoryError::UnknownId(0)));
+    assert!(matches!(mm.delete(1), Err(MemoryError::StaleHandle { staleness: Staleness::Freed, .. })));
+}
+
+#[test]
+fn test_shadow_memory() {
+    let config = MemoryConfig::new().with_heap_size(1024);
+    let mut mm = MemoryManager::with_policy_name(config, "best-fit").unwrap();
+    assert_eq!(mm.read_raw(0, 4), Ok(vec![0; 4]));
+    mm.enable_shadow(ShadowConfig::new().with_redzone(16).with_quarantine(64)).unwrap();
+
+    // The block gets a redzone after its 20 requested bytes, and only written bytes are addressable
+    mm.execute_command("INSERT 20 hello");
+    let states: Vec<_> = [0, 5, 20, 35, 36].iter().map(|&address| mm.shadow_state(address).unwrap()).collect();
+    assert_eq!(
+        states,
+        [ShadowState::Addressable, ShadowState::Uninitialized, ShadowState::Redzone, ShadowState::Redzone, ShadowState::Unallocated]
+    );
+    assert_eq!(mm.read_raw(0, 5), Ok(b"hello".to_vec()));
+    let error = mm.read_raw(3, 4).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "uninitialized-read on address 0x0005: READ of size 4; 0x0005 is located 5 bytes inside the 20-byte region of block 0 [0x0000, 0x0014)"
+    );
+    mm.write_raw(5, b"!!").unwrap();
+    assert_eq!(mm.read_raw(3, 4), Ok(b"lo!!".to_vec()));
+    let error = mm.write_raw(18, b"abcd").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "heap-buffer-overflow on address 0x0014: WRITE of size 4; 0x0014 is located 0 bytes to the right of the 20-byte region of block 0 [0x0000, 0x0014)"
+    );
+    assert!(matches!(mm.update(0, &[b'x'; 21]), Ok(1)));
+
+    // Freed blocks stay poisoned in the quarantine until more than 64 bytes wait behind them
+    assert!(matches!(
+        mm.read_raw(0, 1),
+        Err(MemoryError::BadAccess(access)) if access.kind == AccessError::UseAfterFree && access.block == Some(0)
+    ));
+    mm.execute_command("INSERT 10 a; INSERT 10 b");
+    assert!(mm.read(2).unwrap().start >= 36);
+    assert!(mm.dump().contains("Quarantine: 1 block(s), 36 bytes held back from the policy"));
+    assert!(mm.verify().is_ok());
+    mm.execute_command("DELETE 2; DELETE 3");
+    assert_eq!(mm.execute_command("INSERT 20 again").to_string(), "INSERT success: ID = 4");
+    assert_eq!(mm.read(4).unwrap().start, 0);
+    assert!(mm.verify().is_ok());
+
+    assert_eq!(mm.read_raw(1020, 8), Err(MemoryError::AddressOutOfRange { address: 1020, len: 8, heap_size: 1024 }));
+    assert!(mm.enable_shadow(ShadowConfig::new()).is_err());
+}
+
+#[test]
+fn test_raw_access_commands() {
+    let mut mm = MemoryManager::new();
+    mm.execute_command("INSERT 8 hello");
+    let lines = [
+        ("PEEK 0 5", "PEEK success: 5 byte(s) at 0x0000: 68 65 6C 6C 6F |hello|"),
+        ("POKE 0x0 0x48", "POKE success: 1 byte(s) written at 0x0000"),
+        ("READ 0", "READ data: Start Address: 0x0000, End Address: 0x0004, Status: Allocated, Size: 5 bytes, Data: 'Hello'"),
+        ("WRITE STRICT 6 abc", "WRITE error: Access of 3 bytes at 0x0006 runs past the end of block 0"),
+        ("WRITE 6 abc", "WRITE success: 3 byte(s) written at 0x0006"),
+        ("PEEK STRICT 7 2", "PEEK error: Access of 2 bytes at 0x0007 runs past the end of block 0"),
+        ("PEEK STRICT 3 18446744073709551615", "PEEK error: Access of 18446744073709551615 bytes at 0x0003 runs past the end of block 0"),
+        ("POKE STRICT 0x20 1", "POKE error: Access of 1 bytes at 0x0020 does not start in an allocated block"),
+        ("PEEK 5 4", "PEEK success: 4 byte(s) at 0x0005: 00 61 62 63 |.abc|"),
+        ("WHOIS 3", "WHOIS success: 0x0003 is in allocated block 0 (0x0000-0x0007, 8 bytes), 3 bytes from its start"),
+        ("WHOIS 0x18", "WHOIS success: 0x0018 is in free block (0x0010-0x001F, 16 bytes), 8 bytes from its start"),
+        ("PEEK 0xFFFF 2", "PEEK error: Access of 2 bytes at 0xFFFF is outside the heap of 65536 bytes"),
+    ];
+    for (line, expected) in lines {
+        assert_eq!(mm.execute_command(line).to_string(), expected, "{}", line);
+    }
+
+    let command = parse_line("poke strict 16 1 0xff", 1).unwrap().remove(0);
+    assert_eq!(command, Command::Poke { address: 16, bytes: vec![1, 255], strict: true });
+    assert_eq!(command.to_string(), "POKE STRICT 0x0010 0x01 0xFF");
+    assert_eq!(parse_line(&command.to_string(), 1).unwrap(), vec![command]);
+    assert_eq!(parse_line("POKE 0 256", 1).unwrap_err().message, "invalid byte '256'");
+    assert_eq!(parse_line("WHOIS", 1).unwrap_err().message, "missing address");
+}
+
+#[test]
+fn test_hexdump_and_map() {
+    let mut mm = MemoryManager::with_policy_name(MemoryConfig::new().with_heap_size(1024), "best-fit").unwrap();
+    mm.execute_command("INSERT 16 0123456789abcdef; INSERT 32 hi");
+    let hexdump = mm.execute_command("HEXDUMP").to_string();
+    let lines: Vec<&str> = hexdump.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "00000000: 3031 3233 3435 3637 3839 6162 6364 6566  0123456789abcdef  block 0 at +0 (16 bytes)");
+    assert_eq!(lines[1], "00000010: 6869 0000 0000 0000 0000 0000 0000 0000  hi..............  block 1 at +0 (32 bytes)");
+    assert_eq!(lines[2], "00000020: 0000 0000 0000 0000 0000 0000 0000 0000  ................");
+    assert!(lines[3].ends_with("free at +0 (976 bytes)"));
+    assert_eq!(&lines[4..], ["*", "000003f0: 0000 0000 0000 0000 0000 0000 0000 0000  ................"]);
+
+    // A range shows the region it starts in, and a partial last line keeps the columns aligned
+    assert_eq!(
+        mm.hexdump(0x12, 6).unwrap(),
+        "00000012: 0000 0000 0000                           ......            in block 1"
+    );
+    assert_eq!(mm.execute_command("HEXDUMP 1020 8").to_string(), "HEXDUMP error: Access of 8 bytes at 0x03FC is outside the heap of 1024 bytes");
+
+    assert_eq!(
+        mm.execute_command("MAP").to_string(),
+        format!("Memory map: 64 cells of 16 bytes\n[#++{}]\n'#' in use, '+' partially used, '.' free", ".".repeat(61))
+    );
+    let command = parse_line("hexdump 0x10 32", 1).unwrap().remove(0);
+    assert_eq!(command.to_string(), "HEXDUMP 0x0010 32");
+}